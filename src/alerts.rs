@@ -0,0 +1,570 @@
+use crate::financial_analysis::{working_capital_per_share, StockReport};
+use crate::storage::{unix_timestamp, Storage};
+use anyhow::{Error, Result};
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    // Price below a fraction of the net working capital per share, Graham used 2/3
+    PriceBelowWorkingCapital { fraction: f64 },
+    // All required criteria pass
+    PassesAllCriteria,
+    // Margin of safety to the intrinsic value above the minimum, in percent / 100
+    MarginOfSafetyAbove { min: f64 },
+}
+
+impl AlertCondition {
+    // Whether the condition only depends on the fundamentals and the price, so that it can be
+    // evaluated on streamed quotes without screening the stock again
+    pub fn is_price_based(&self) -> bool {
+        !matches!(self, AlertCondition::PassesAllCriteria)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AlertRule {
+    pub name: String,
+    pub condition: AlertCondition,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpSecurity {
+    None,
+    StartTls,
+    Tls,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    #[serde(default = "default_smtp_security")]
+    pub security: SmtpSecurity,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+fn default_smtp_security() -> SmtpSecurity {
+    SmtpSecurity::StartTls
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct AlertConfig {
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    #[serde(default)]
+    pub smtp: Option<SmtpConfig>,
+}
+
+impl AlertConfig {
+    pub fn from_json_value(json: serde_json::Value) -> Self {
+        if json.is_null() {
+            return Self::default();
+        }
+        serde_json::from_value(json).unwrap()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Alert {
+    pub rule: String,
+    pub symbol: String,
+    pub message: String,
+    pub price: f64,
+    pub triggered_at: i64, // Unix timestamp
+}
+
+// Returns the alert message when the condition holds for the report
+pub fn check_condition(condition: &AlertCondition, report: &StockReport) -> Option<String> {
+    let price = report.quote.c;
+    match condition {
+        AlertCondition::PriceBelowWorkingCapital { fraction } => {
+            let working_capital_per_share =
+                working_capital_per_share(&report.financials, &report.information)?;
+            let limit = fraction * working_capital_per_share;
+            (price < limit).then(|| {
+                format!(
+                    "Price {price:.2} is below {fraction:.2} of the net working capital per share {working_capital_per_share:.2}"
+                )
+            })
+        }
+        AlertCondition::PassesAllCriteria => report
+            .passed
+            .then(|| "Passes all screening criteria".to_string()),
+        AlertCondition::MarginOfSafetyAbove { min } => {
+            let margin_of_safety = report.margin_of_safety?;
+            (margin_of_safety > *min).then(|| {
+                format!(
+                    "Margin of safety {:.1}% is above {:.1}% (price {:.2}, intrinsic value {:.2})",
+                    100.0 * margin_of_safety,
+                    100.0 * min,
+                    price,
+                    report.intrinsic_value.unwrap_or_default()
+                )
+            })
+        }
+    }
+}
+
+pub struct AlertDispatcher {
+    config: AlertConfig,
+    client: reqwest::Client,
+}
+
+impl AlertDispatcher {
+    pub fn new(config: AlertConfig) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    // Evaluates every rule on the reports and delivers the alerts whose condition started to hold
+    // since the previous evaluation. Returns the delivered alerts.
+    pub async fn process_reports(
+        &self,
+        storage: &Storage,
+        reports: &[StockReport],
+    ) -> Result<Vec<Alert>> {
+        self.process(storage, reports, false).await
+    }
+
+    // Same for reports refreshed from streamed quotes, evaluating only the price based rules
+    pub async fn process_quote_reports(
+        &self,
+        storage: &Storage,
+        reports: &[StockReport],
+    ) -> Result<Vec<Alert>> {
+        self.process(storage, reports, true).await
+    }
+
+    async fn process(
+        &self,
+        storage: &Storage,
+        reports: &[StockReport],
+        price_based_only: bool,
+    ) -> Result<Vec<Alert>> {
+        let rules: Vec<&AlertRule> = self
+            .config
+            .rules
+            .iter()
+            .filter(|rule| !price_based_only || rule.condition.is_price_based())
+            .collect();
+        let mut alerts = Vec::new();
+        for report in reports {
+            let symbol = &report.stock_info.symbol;
+            for rule in &rules {
+                match check_condition(&rule.condition, report) {
+                    Some(message) => {
+                        if !storage.is_alert_active(&rule.name, symbol)? {
+                            alerts.push(Alert {
+                                rule: rule.name.clone(),
+                                symbol: symbol.clone(),
+                                message,
+                                price: report.quote.c,
+                                triggered_at: unix_timestamp(),
+                            });
+                        }
+                    }
+                    None => storage.set_alert_active(&rule.name, symbol, false)?,
+                }
+            }
+        }
+        if alerts.is_empty() {
+            return Ok(alerts);
+        }
+
+        self.deliver(&alerts).await?;
+        for alert in &alerts {
+            storage.set_alert_active(&alert.rule, &alert.symbol, true)?;
+        }
+        Ok(alerts)
+    }
+
+    pub async fn deliver(&self, alerts: &[Alert]) -> Result<()> {
+        for alert in alerts {
+            println!("ALERT {}: {}: {}", alert.rule, alert.symbol, alert.message);
+        }
+        // The alerts count as delivered once any channel has sent them, so that a failing channel
+        // does not make the next run send them again on the channels that worked
+        let mut delivered = false;
+        let mut errors = Vec::new();
+        if let Some(webhook_url) = &self.config.webhook_url {
+            match self.send_webhook(webhook_url, alerts).await {
+                Ok(()) => delivered = true,
+                Err(error) => errors.push(format!("Webhook delivery failed: {error}")),
+            }
+        }
+        if let Some(smtp_config) = &self.config.smtp {
+            match send_email(smtp_config, alerts).await {
+                Ok(()) => delivered = true,
+                Err(error) => errors.push(format!("Email delivery failed: {error}")),
+            }
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        if !delivered {
+            return Err(Error::msg(errors.join("; ")));
+        }
+        for error in errors {
+            println!("{error}");
+        }
+        Ok(())
+    }
+
+    async fn send_webhook(&self, webhook_url: &str, alerts: &[Alert]) -> Result<()> {
+        let payload = serde_json::json!({ "alerts": alerts });
+        let response = self.client.post(webhook_url).json(&payload).send().await?;
+        if !response.status().is_success() {
+            return Err(Error::msg(format!(
+                "Webhook responded with {}",
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+// Evaluates the alert rules on the reports of a stored run
+pub async fn send_run_alerts(
+    alert_config: &AlertConfig,
+    storage: &Storage,
+    run_id: i64,
+) -> Result<Vec<Alert>> {
+    if alert_config.rules.is_empty() {
+        return Ok(Vec::new());
+    }
+    let reports = storage.run_reports(run_id)?;
+    let alerts = AlertDispatcher::new(alert_config.clone())
+        .process_reports(storage, &reports)
+        .await?;
+    println!("Sent {} alerts", alerts.len());
+    Ok(alerts)
+}
+
+async fn send_email(smtp_config: &SmtpConfig, alerts: &[Alert]) -> Result<()> {
+    let mut builder = Message::builder()
+        .from(smtp_config.from.parse::<Mailbox>()?)
+        .subject(format!("{} stock alert(s)", alerts.len()));
+    for to in &smtp_config.to {
+        builder = builder.to(to.parse::<Mailbox>()?);
+    }
+    let body = alerts
+        .iter()
+        .map(|alert| format!("{} [{}]: {}", alert.symbol, alert.rule, alert.message))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let email = builder.body(body)?;
+
+    let mut transport = match smtp_config.security {
+        SmtpSecurity::None => {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&smtp_config.host)
+        }
+        SmtpSecurity::StartTls => {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp_config.host)?
+        }
+        SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp_config.host)?,
+    }
+    .port(smtp_config.port);
+    if let (Some(username), Some(password)) = (&smtp_config.username, &smtp_config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+    transport.build().send(email).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::report;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    fn test_rules() -> Vec<AlertRule> {
+        serde_json::from_value(serde_json::json!([
+            {
+                "name": "below_working_capital",
+                "condition": { "type": "price_below_working_capital", "fraction": 0.667 }
+            },
+            { "name": "passes", "condition": { "type": "passes_all_criteria" } },
+            {
+                "name": "margin_of_safety",
+                "condition": { "type": "margin_of_safety_above", "min": 0.4 }
+            }
+        ]))
+        .unwrap()
+    }
+
+    // Replies 200 to every request and forwards the request bodies
+    async fn spawn_webhook_stand_in() -> (String, mpsc::UnboundedReceiver<serde_json::Value>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).await.unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).await.unwrap();
+                sender.send(serde_json::from_slice(&body).unwrap()).unwrap();
+                reader
+                    .into_inner()
+                    .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                    .await
+                    .unwrap();
+            }
+        });
+        (format!("http://{address}/alerts"), receiver)
+    }
+
+    // Minimal SMTP server accepting a single message and forwarding its data
+    async fn spawn_smtp_stand_in() -> (u16, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            writer.write_all(b"220 localhost ESMTP\r\n").await.unwrap();
+            let mut data = String::new();
+            let mut in_data = false;
+            while let Some(line) = lines.next_line().await.unwrap() {
+                if in_data {
+                    if line == "." {
+                        in_data = false;
+                        sender.send(data.clone()).unwrap();
+                        writer.write_all(b"250 OK\r\n").await.unwrap();
+                    } else {
+                        data += &line;
+                        data += "\n";
+                    }
+                    continue;
+                }
+                let command = line.to_uppercase();
+                let reply: &[u8] = if command.starts_with("EHLO") {
+                    b"250-localhost\r\n250 8BITMIME\r\n"
+                } else if command.starts_with("DATA") {
+                    in_data = true;
+                    b"354 End data with <CR><LF>.<CR><LF>\r\n"
+                } else if command.starts_with("QUIT") {
+                    writer.write_all(b"221 Bye\r\n").await.unwrap();
+                    break;
+                } else {
+                    b"250 OK\r\n"
+                };
+                writer.write_all(reply).await.unwrap();
+            }
+        });
+        (port, receiver)
+    }
+
+    // SMTP server that refuses every connection with a permanent error
+    async fn spawn_failing_smtp_stand_in() -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let _ = stream.write_all(b"554 No service\r\n").await;
+            }
+        });
+        port
+    }
+
+    fn smtp_config(port: u16) -> SmtpConfig {
+        SmtpConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            security: SmtpSecurity::None,
+            username: None,
+            password: None,
+            from: "screener@example.com".to_string(),
+            to: vec!["investor@example.com".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_example_config() -> Result<()> {
+        let settings = crate::settings::load_settings("config/example.json")?;
+        let alert_config = AlertConfig::from_json_value(settings["alerts"].clone());
+        assert_eq!(alert_config.rules.len(), 3);
+        assert!(alert_config.webhook_url.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_conditions() {
+        let rules = test_rules();
+        // Working capital per share is 40, intrinsic value 88.39
+        let cheap = report("CHEAP", 10.0, 20.0);
+        let fair = report("FAIR", 10.0, 30.0);
+        let dear = report("DEAR", 30.0, 30.0);
+
+        assert!(check_condition(&rules[0].condition, &cheap).is_some());
+        assert!(check_condition(&rules[0].condition, &fair).is_none());
+        assert!(check_condition(&rules[1].condition, &fair).is_some());
+        assert!(check_condition(&rules[1].condition, &dear).is_none());
+        let message = check_condition(&rules[2].condition, &fair).unwrap();
+        assert!(message.starts_with("Margin of safety 66.1%"));
+    }
+
+    #[tokio::test]
+    async fn test_alerts_are_delivered_once() -> Result<()> {
+        let (webhook_url, mut webhook_payloads) = spawn_webhook_stand_in().await;
+        let dispatcher = AlertDispatcher::new(AlertConfig {
+            rules: test_rules(),
+            webhook_url: Some(webhook_url),
+            smtp: None,
+        });
+        let storage = Storage::open_in_memory()?;
+
+        let alerts = dispatcher
+            .process_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await?;
+        assert_eq!(alerts.len(), 2);
+        let payload = webhook_payloads.recv().await.unwrap();
+        assert_eq!(payload["alerts"][0]["symbol"], "FAIR");
+        assert_eq!(payload["alerts"][0]["rule"], "passes");
+
+        // Same state again, nothing new to send
+        let alerts = dispatcher
+            .process_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await?;
+        assert!(alerts.is_empty());
+
+        // Stops passing, then passes again
+        dispatcher
+            .process_reports(&storage, &[report("FAIR", 30.0, 30.0)])
+            .await?;
+        let alerts = dispatcher
+            .process_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await?;
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "passes");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_quote_alerts() -> Result<()> {
+        let (webhook_url, mut webhook_payloads) = spawn_webhook_stand_in().await;
+        let dispatcher = AlertDispatcher::new(AlertConfig {
+            rules: test_rules(),
+            webhook_url: Some(webhook_url),
+            smtp: None,
+        });
+        let storage = Storage::open_in_memory()?;
+
+        // Only the margin of safety holds among the price based rules, passing all criteria
+        // needs a screening
+        let alerts = dispatcher
+            .process_quote_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await?;
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "margin_of_safety");
+        assert!(webhook_payloads.recv().await.is_some());
+
+        // Shares the de-duplication with the screening runs
+        assert!(dispatcher
+            .process_quote_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await?
+            .is_empty());
+        let alerts = dispatcher
+            .process_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await?;
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].rule, "passes");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_email_delivery() -> Result<()> {
+        let (port, mut messages) = spawn_smtp_stand_in().await;
+        let dispatcher = AlertDispatcher::new(AlertConfig {
+            rules: Vec::new(),
+            webhook_url: None,
+            smtp: Some(smtp_config(port)),
+        });
+
+        let alert = Alert {
+            rule: "passes".to_string(),
+            symbol: "FAIR".to_string(),
+            message: "Passes all screening criteria".to_string(),
+            price: 30.0,
+            triggered_at: 0,
+        };
+        dispatcher.deliver(&[alert]).await?;
+
+        let message = messages.recv().await.unwrap();
+        assert!(message.contains("To: investor@example.com"));
+        assert!(message.contains("Subject: 1 stock alert(s)"));
+        assert!(message.contains("FAIR [passes]: Passes all screening criteria"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_partial_delivery_is_not_repeated() -> Result<()> {
+        let (webhook_url, mut webhook_payloads) = spawn_webhook_stand_in().await;
+        let smtp_port = spawn_failing_smtp_stand_in().await;
+        let dispatcher = AlertDispatcher::new(AlertConfig {
+            rules: test_rules(),
+            webhook_url: Some(webhook_url),
+            smtp: Some(smtp_config(smtp_port)),
+        });
+        let storage = Storage::open_in_memory()?;
+
+        // The webhook delivers the alerts even though the email fails
+        let alerts = dispatcher
+            .process_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await?;
+        assert_eq!(alerts.len(), 2);
+        assert!(webhook_payloads.recv().await.is_some());
+
+        // So the next run does not send them on the webhook again
+        let alerts = dispatcher
+            .process_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await?;
+        assert!(alerts.is_empty());
+        assert!(webhook_payloads.try_recv().is_err());
+
+        // With no channel delivering, the alerts stay pending for the next run
+        let dispatcher = AlertDispatcher::new(AlertConfig {
+            rules: test_rules(),
+            webhook_url: None,
+            smtp: Some(smtp_config(smtp_port)),
+        });
+        let storage = Storage::open_in_memory()?;
+        assert!(dispatcher
+            .process_reports(&storage, &[report("FAIR", 10.0, 30.0)])
+            .await
+            .is_err());
+        assert!(!storage.is_alert_active("passes", "FAIR")?);
+        Ok(())
+    }
+}
@@ -0,0 +1,200 @@
+use crate::fundamentals_history::ReportedStatements;
+use crate::stock_data_fetching::CompanyInformation;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AltmanConfig {
+    // Whether stocks in the distress zone fail and are excluded by check_stock
+    #[serde(default)]
+    pub exclude_distress: bool,
+    // Finnhub industries of US companies scored with the original manufacturer model, the others
+    // get the Z'' model for non-manufacturers
+    #[serde(default = "default_manufacturing_industries")]
+    pub manufacturing_industries: Vec<String>,
+}
+
+fn default_manufacturing_industries() -> Vec<String> {
+    [
+        "Aerospace & Defense",
+        "Auto Components",
+        "Automobiles",
+        "Building",
+        "Chemicals",
+        "Electrical Equipment",
+        "Industrial Conglomerates",
+        "Machinery",
+        "Metals & Mining",
+        "Packaging",
+        "Paper & Forest",
+        "Semiconductors",
+        "Textiles, Apparel & Luxury Goods",
+    ]
+    .iter()
+    .map(|industry| industry.to_string())
+    .collect()
+}
+
+impl Default for AltmanConfig {
+    fn default() -> Self {
+        Self {
+            exclude_distress: false,
+            manufacturing_industries: default_manufacturing_industries(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AltmanModel {
+    Manufacturer,    // Z, public manufacturers
+    NonManufacturer, // Z'', non-manufacturers and companies outside the US
+}
+
+impl AltmanModel {
+    // Upper bounds of the distress and grey zones
+    fn zone_limits(&self) -> (f64, f64) {
+        match self {
+            AltmanModel::Manufacturer => (1.81, 2.99),
+            AltmanModel::NonManufacturer => (1.1, 2.6),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AltmanZone {
+    Safe,
+    Grey,
+    Distress,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AltmanZScore {
+    pub year: i32,
+    pub model: AltmanModel,
+    pub z_score: f64,
+    pub zone: AltmanZone,
+    pub distress_limit: f64, // Scores below it are in the distress zone
+}
+
+pub fn altman_model(information: &CompanyInformation, altman_config: &AltmanConfig) -> AltmanModel {
+    if information.country == "US"
+        && altman_config
+            .manufacturing_industries
+            .contains(&information.finnhub_industry)
+    {
+        AltmanModel::Manufacturer
+    } else {
+        AltmanModel::NonManufacturer
+    }
+}
+
+// From the latest reported statements, the market value of equity is the current market cap
+pub fn altman_z_score(
+    statements: &[ReportedStatements],
+    information: &CompanyInformation,
+    altman_config: &AltmanConfig,
+) -> Result<AltmanZScore> {
+    let latest = statements
+        .first()
+        .ok_or_else(|| Error::msg("No reported statements for the Altman Z-score"))?;
+    let missing = || Error::msg("Missing items for the Altman Z-score");
+    let total_assets = latest.total_assets.ok_or_else(missing)?;
+    let total_liabilities = latest.total_liabilities.ok_or_else(missing)?;
+    if total_assets <= 0.0 || total_liabilities <= 0.0 {
+        return Err(Error::msg("No total assets or liabilities"));
+    }
+    let working_capital = latest.working_capital().ok_or_else(missing)?;
+    let retained_earnings = latest.retained_earnings.ok_or_else(missing)?;
+    let ebit = latest.operating_income.ok_or_else(missing)?;
+
+    let model = altman_model(information, altman_config);
+    let z_score = match model {
+        AltmanModel::Manufacturer => {
+            let revenue = latest.revenue.ok_or_else(missing)?;
+            1.2 * working_capital / total_assets
+                + 1.4 * retained_earnings / total_assets
+                + 3.3 * ebit / total_assets
+                + 0.6 * information.market_cap / total_liabilities
+                + revenue / total_assets
+        }
+        AltmanModel::NonManufacturer => {
+            let book_equity = latest.stockholders_equity.ok_or_else(missing)?;
+            6.56 * working_capital / total_assets
+                + 3.26 * retained_earnings / total_assets
+                + 6.72 * ebit / total_assets
+                + 1.05 * book_equity / total_liabilities
+        }
+    };
+
+    let (distress_limit, grey_limit) = model.zone_limits();
+    let zone = if z_score < distress_limit {
+        AltmanZone::Distress
+    } else if z_score <= grey_limit {
+        AltmanZone::Grey
+    } else {
+        AltmanZone::Safe
+    };
+    Ok(AltmanZScore {
+        year: latest.year,
+        model,
+        z_score,
+        zone,
+        distress_limit,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::information;
+
+    fn statements(retained_earnings: f64, operating_income: f64) -> ReportedStatements {
+        ReportedStatements {
+            year: 2022,
+            total_assets: Some(1000.0),
+            total_current_assets: Some(400.0),
+            total_current_liabilities: Some(200.0),
+            total_liabilities: Some(600.0),
+            retained_earnings: Some(retained_earnings),
+            stockholders_equity: Some(400.0),
+            revenue: Some(1500.0),
+            operating_income: Some(operating_income),
+            ..ReportedStatements::default()
+        }
+    }
+
+    #[test]
+    fn test_altman_z_score() -> Result<()> {
+        let altman_config = AltmanConfig::default();
+        let mut manufacturer = information("MFG");
+        manufacturer.finnhub_industry = "Machinery".to_string();
+        manufacturer.market_cap = 1200.0;
+
+        // 0.24 + 0.42 + 0.495 + 1.2 + 1.5
+        let z = altman_z_score(&[statements(300.0, 150.0)], &manufacturer, &altman_config)?;
+        assert_eq!(z.model, AltmanModel::Manufacturer);
+        assert!((z.z_score - 3.855).abs() < 1e-9);
+        assert_eq!(z.zone, AltmanZone::Safe);
+
+        // The same company outside the US gets the Z'' model: 1.312 - 0.978 - 0.336 + 0.7
+        manufacturer.country = "NO".to_string();
+        let z = altman_z_score(&[statements(-300.0, -50.0)], &manufacturer, &altman_config)?;
+        assert_eq!(z.model, AltmanModel::NonManufacturer);
+        assert!((z.z_score - 0.698).abs() < 1e-9);
+        assert_eq!(z.zone, AltmanZone::Distress);
+        assert_eq!(z.distress_limit, 1.1);
+
+        let retailer = information("RTL");
+        let z = altman_z_score(&[statements(0.0, 0.0)], &retailer, &altman_config)?;
+        assert_eq!(z.model, AltmanModel::NonManufacturer);
+        assert_eq!(z.zone, AltmanZone::Grey);
+
+        assert!(altman_z_score(&[], &retailer, &altman_config).is_err());
+        let mut incomplete = statements(0.0, 0.0);
+        incomplete.retained_earnings = None;
+        assert!(altman_z_score(&[incomplete], &retailer, &altman_config).is_err());
+        Ok(())
+    }
+}
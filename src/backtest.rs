@@ -0,0 +1,706 @@
+use crate::financial_analysis::{evaluate_stock, AnalysisConfig, StockAnalyzer};
+use crate::fundamentals_history::FundamentalsHistory;
+use crate::stock_data_fetching::{
+    CompanyFinancials, CompanyInformation, CompanyQuote, StockInfo, NUM_REPORTED_PERIODS,
+};
+use crate::storage::Storage;
+use anyhow::{Error, Result};
+use chrono::{Duration, Months, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RebalanceFrequency {
+    Monthly,
+    Quarterly,
+    Yearly,
+}
+
+impl RebalanceFrequency {
+    pub fn months(&self) -> u32 {
+        match self {
+            RebalanceFrequency::Monthly => 1,
+            RebalanceFrequency::Quarterly => 3,
+            RebalanceFrequency::Yearly => 12,
+        }
+    }
+
+    pub fn periods_per_year(&self) -> f64 {
+        12.0 / self.months() as f64
+    }
+}
+
+impl std::str::FromStr for RebalanceFrequency {
+    type Err = Error;
+
+    fn from_str(frequency: &str) -> Result<Self> {
+        match frequency {
+            "monthly" => Ok(RebalanceFrequency::Monthly),
+            "quarterly" => Ok(RebalanceFrequency::Quarterly),
+            "yearly" => Ok(RebalanceFrequency::Yearly),
+            _ => Err(Error::msg(format!(
+                "Unknown rebalancing frequency {frequency}, use monthly, quarterly or yearly"
+            ))),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BacktestConfig {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub rebalance: RebalanceFrequency,
+    pub benchmark: String, // Symbol of e.g. an index ETF
+}
+
+// Fundamentals as they were known on a date, with the price their ratios were computed at
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PointInTimeFundamentals {
+    pub financials: CompanyFinancials,
+    pub information: CompanyInformation,
+    pub price: f64,
+}
+
+pub trait HistoricalData {
+    // Latest fundamentals known on the date, later revisions must not leak into earlier dates
+    fn fundamentals_as_of(&self, symbol: &str, date: NaiveDate) -> Option<PointInTimeFundamentals>;
+    // Last close on or before the date
+    fn price_on(&self, symbol: &str, date: NaiveDate) -> Option<f64>;
+}
+
+#[derive(Default)]
+pub struct HistoricalDataSet {
+    fundamentals: BTreeMap<String, BTreeMap<NaiveDate, PointInTimeFundamentals>>,
+    prices: BTreeMap<String, BTreeMap<NaiveDate, f64>>,
+    filings: BTreeMap<String, FundamentalsHistory>,
+}
+
+impl HistoricalDataSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_fundamentals(
+        &mut self,
+        symbol: &str,
+        date: NaiveDate,
+        fundamentals: PointInTimeFundamentals,
+    ) {
+        self.fundamentals
+            .entry(symbol.to_string())
+            .or_default()
+            .insert(date, fundamentals);
+    }
+
+    pub fn add_price(&mut self, symbol: &str, date: NaiveDate, price: f64) {
+        self.prices
+            .entry(symbol.to_string())
+            .or_default()
+            .insert(date, price);
+    }
+
+    // The balance sheet of the latest filing public on a date replaces the one of the fundamentals
+    pub fn add_filings(&mut self, symbol: &str, history: FundamentalsHistory) {
+        self.filings.insert(symbol.to_string(), history);
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        self.fundamentals.keys().cloned().collect()
+    }
+
+    // The stored snapshots are point in time by construction, they hold what was fetched on the
+    // day of the run. Their quotes are added as prices. The universe is limited to the stocks that
+    // were screened, so delisted stocks that were never stored are missing (survivorship bias),
+    // and nothing is known before the first stored run.
+    pub fn from_snapshots(storage: &Storage) -> Result<Self> {
+        let mut data = Self::new();
+        for symbol in storage.snapshot_symbols()? {
+            for snapshot in storage.snapshots(&symbol)? {
+                let date = Utc
+                    .timestamp_opt(snapshot.fetched_at, 0)
+                    .single()
+                    .ok_or_else(|| Error::msg("Invalid snapshot timestamp"))?
+                    .date_naive();
+                data.add_price(&symbol, date, snapshot.quote.c);
+                data.add_fundamentals(
+                    &symbol,
+                    date,
+                    PointInTimeFundamentals {
+                        financials: snapshot.financials,
+                        information: snapshot.information,
+                        price: snapshot.quote.c,
+                    },
+                );
+            }
+        }
+        Ok(data)
+    }
+
+    pub async fn fetch_filings(
+        &mut self,
+        analyzer: &mut StockAnalyzer,
+        symbols: &[String],
+    ) -> Result<()> {
+        for symbol in symbols {
+            match analyzer.get_fundamentals_history(symbol).await {
+                Ok(history) => self.add_filings(symbol, history),
+                Err(error) => println!("Could not fetch the filings of {symbol}: {error}"),
+            }
+        }
+        Ok(())
+    }
+
+    // Adds the daily closes from Finnhub, starting a few days early so that the first
+    // rebalancing has a price even when it falls on a holiday
+    pub async fn fetch_prices(
+        &mut self,
+        analyzer: &mut StockAnalyzer,
+        symbols: &[String],
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> Result<()> {
+        let unix_time = |date: NaiveDate| {
+            date.and_hms_opt(0, 0, 0)
+                .map_or(0, |date_time| Utc.from_utc_datetime(&date_time).timestamp())
+        };
+        let from = unix_time(start - Duration::days(10));
+        let to = unix_time(end + Duration::days(1));
+        for symbol in symbols {
+            match analyzer.get_price_history(symbol, from, to).await {
+                Ok(prices) => {
+                    for (date, price) in prices {
+                        self.add_price(symbol, date, price);
+                    }
+                }
+                Err(error) => println!("Could not fetch the prices of {symbol}: {error}"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl HistoricalData for HistoricalDataSet {
+    fn fundamentals_as_of(&self, symbol: &str, date: NaiveDate) -> Option<PointInTimeFundamentals> {
+        let mut fundamentals = self
+            .fundamentals
+            .get(symbol)?
+            .range(..=date)
+            .next_back()
+            .map(|(_, fundamentals)| fundamentals.clone())?;
+        if let Some(history) = self.filings.get(symbol) {
+            if let Some(filing) = history.as_of(date) {
+                // The snapshot keeps the items that the filing does not report
+                let balance_sheet = filing.balance_sheet;
+                let financials = &mut fundamentals.financials;
+                financials.total_current_assets = balance_sheet
+                    .total_current_assets
+                    .or(financials.total_current_assets);
+                financials.total_current_liabilities = balance_sheet
+                    .total_current_liabilities
+                    .or(financials.total_current_liabilities);
+                financials.total_current_long_term_debt = balance_sheet
+                    .total_current_long_term_debt
+                    .or(financials.total_current_long_term_debt);
+                financials.total_long_term_debt = balance_sheet
+                    .total_long_term_debt
+                    .or(financials.total_long_term_debt);
+            }
+            fundamentals.financials.reported_statements =
+                history.statements_as_of(date, NUM_REPORTED_PERIODS);
+        }
+        Some(fundamentals)
+    }
+
+    fn price_on(&self, symbol: &str, date: NaiveDate) -> Option<f64> {
+        self.prices
+            .get(symbol)?
+            .range(..=date)
+            .next_back()
+            .map(|(_, price)| *price)
+    }
+}
+
+// Start of every holding period, followed by the end of the backtest
+pub fn rebalance_dates(config: &BacktestConfig) -> Vec<NaiveDate> {
+    let mut dates = Vec::new();
+    let mut period = 0;
+    while let Some(date) = config
+        .start
+        .checked_add_months(Months::new(period * config.rebalance.months()))
+    {
+        if date >= config.end {
+            break;
+        }
+        dates.push(date);
+        period += 1;
+    }
+    dates.push(config.end);
+    dates
+}
+
+// Scales the price based ratios of the fundamentals to another price
+pub fn reprice(
+    fundamentals: &PointInTimeFundamentals,
+    price: f64,
+) -> (CompanyFinancials, CompanyInformation) {
+    let mut financials = fundamentals.financials.clone();
+    let mut information = fundamentals.information.clone();
+    if fundamentals.price > 0.0 {
+        let scale = price / fundamentals.price;
+        financials.pe_ratio = financials.pe_ratio.map(|pe_ratio| pe_ratio * scale);
+        financials.pb_ratio = financials.pb_ratio.map(|pb_ratio| pb_ratio * scale);
+        financials.ps_ratio = financials.ps_ratio.map(|ps_ratio| ps_ratio * scale);
+        information.market_cap *= scale;
+    }
+    (financials, information)
+}
+
+// Symbols passing every required criterion with the data known on the date
+pub fn select_stocks(
+    data: &impl HistoricalData,
+    universe: &[String],
+    date: NaiveDate,
+    analysis_config: &AnalysisConfig,
+) -> Vec<String> {
+    let mut selected = Vec::new();
+    for symbol in universe {
+        let (fundamentals, price) = match (
+            data.fundamentals_as_of(symbol, date),
+            data.price_on(symbol, date),
+        ) {
+            (Some(fundamentals), Some(price)) => (fundamentals, price),
+            _ => continue,
+        };
+        let (financials, information) = reprice(&fundamentals, price);
+        let quote = CompanyQuote {
+            c: price,
+            h: price,
+            l: price,
+            o: price,
+            pc: price,
+            t: 0,
+        };
+        let report = evaluate_stock(
+            &StockInfo::from_symbol(symbol),
+            financials,
+            information,
+            quote,
+            analysis_config,
+        );
+        if report.passed {
+            selected.push(symbol.clone());
+        }
+    }
+    selected
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BacktestPeriod {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    pub holdings: Vec<String>, // Equally weighted, empty when the portfolio is in cash
+    pub portfolio_return: f64,
+    pub benchmark_return: f64,
+    pub turnover: f64, // Fraction of the portfolio traded at the start of the period
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PerformanceStats {
+    pub total_return: f64,
+    pub cagr: f64,
+    pub volatility: f64,   // Annualised standard deviation of the period returns
+    pub max_drawdown: f64, // Largest fall from a previous peak, as a positive fraction
+}
+
+pub fn performance_stats(returns: &[f64], years: f64, periods_per_year: f64) -> PerformanceStats {
+    let mut equity = 1.0;
+    let mut peak = 1.0;
+    let mut max_drawdown: f64 = 0.0;
+    for period_return in returns {
+        equity *= 1.0 + period_return;
+        peak = f64::max(peak, equity);
+        max_drawdown = max_drawdown.max((peak - equity) / peak);
+    }
+
+    let cagr = if years > 0.0 {
+        equity.powf(1.0 / years) - 1.0
+    } else {
+        0.0
+    };
+    let volatility = if returns.len() > 1 {
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns
+            .iter()
+            .map(|period_return| (period_return - mean).powi(2))
+            .sum::<f64>()
+            / (returns.len() - 1) as f64;
+        variance.sqrt() * periods_per_year.sqrt()
+    } else {
+        0.0
+    };
+
+    PerformanceStats {
+        total_return: equity - 1.0,
+        cagr,
+        volatility,
+        max_drawdown,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BacktestReport {
+    pub config: BacktestConfig,
+    pub periods: Vec<BacktestPeriod>,
+    pub strategy: PerformanceStats,
+    pub benchmark: PerformanceStats,
+    pub average_turnover: f64, // Per rebalancing, the initial purchase excluded
+}
+
+fn price_return(
+    data: &impl HistoricalData,
+    symbol: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Option<f64> {
+    let start_price = data.price_on(symbol, start)?;
+    let end_price = data.price_on(symbol, end)?;
+    (start_price > 0.0).then(|| end_price / start_price - 1.0)
+}
+
+// Holds an equal weight portfolio of the passing stocks between rebalancing dates. A holding
+// without a later price keeps its last known price.
+pub fn run_backtest(
+    data: &impl HistoricalData,
+    universe: &[String],
+    analysis_config: &AnalysisConfig,
+    config: &BacktestConfig,
+) -> Result<BacktestReport> {
+    if config.start >= config.end {
+        return Err(Error::msg("The backtest must start before it ends"));
+    }
+    if universe.is_empty() {
+        return Err(Error::msg(
+            "No stocks to backtest, store the results of a run first",
+        ));
+    }
+    // Otherwise every period would silently be held in cash
+    if !universe
+        .iter()
+        .any(|symbol| data.fundamentals_as_of(symbol, config.start).is_some())
+    {
+        return Err(Error::msg(format!(
+            "No fundamentals known on {}, the history starts with the first stored run",
+            config.start
+        )));
+    }
+
+    let dates = rebalance_dates(config);
+    let mut periods = Vec::new();
+    let mut drifted_weights: BTreeMap<String, f64> = BTreeMap::new();
+    for window in dates.windows(2) {
+        let (start, end) = (window[0], window[1]);
+        let holdings = select_stocks(data, universe, start, analysis_config);
+
+        let weight = 1.0 / holdings.len().max(1) as f64;
+        let mut traded = 0.0;
+        for symbol in &holdings {
+            traded += (weight - drifted_weights.get(symbol).copied().unwrap_or(0.0)).abs();
+        }
+        for (symbol, drifted_weight) in &drifted_weights {
+            if !holdings.contains(symbol) {
+                traded += drifted_weight;
+            }
+        }
+        // Moving into or out of cash
+        if drifted_weights.is_empty() != holdings.is_empty() {
+            traded += 1.0;
+        }
+
+        let mut returns = BTreeMap::new();
+        for symbol in &holdings {
+            let holding_return = price_return(data, symbol, start, end).unwrap_or(0.0);
+            returns.insert(symbol.clone(), holding_return);
+        }
+        let portfolio_return = returns.values().sum::<f64>() * weight;
+        let benchmark_return =
+            price_return(data, &config.benchmark, start, end).ok_or_else(|| {
+                Error::msg(format!(
+                    "No price of the benchmark {} between {} and {}",
+                    config.benchmark, start, end
+                ))
+            })?;
+
+        // A portfolio that lost everything has nothing left to drift
+        drifted_weights = if portfolio_return > -1.0 {
+            returns
+                .iter()
+                .map(|(symbol, holding_return)| {
+                    let drifted_weight = weight * (1.0 + holding_return) / (1.0 + portfolio_return);
+                    (symbol.clone(), drifted_weight)
+                })
+                .collect()
+        } else {
+            BTreeMap::new()
+        };
+
+        periods.push(BacktestPeriod {
+            start,
+            end,
+            holdings,
+            portfolio_return,
+            benchmark_return,
+            turnover: traded / 2.0,
+        });
+    }
+
+    let years = (config.end - config.start).num_days() as f64 / 365.25;
+    let periods_per_year = config.rebalance.periods_per_year();
+    let portfolio_returns: Vec<f64> = periods
+        .iter()
+        .map(|period| period.portfolio_return)
+        .collect();
+    let benchmark_returns: Vec<f64> = periods
+        .iter()
+        .map(|period| period.benchmark_return)
+        .collect();
+    let rebalancings = &periods[1..];
+    let average_turnover = if rebalancings.is_empty() {
+        0.0
+    } else {
+        rebalancings
+            .iter()
+            .map(|period| period.turnover)
+            .sum::<f64>()
+            / rebalancings.len() as f64
+    };
+
+    Ok(BacktestReport {
+        config: config.clone(),
+        strategy: performance_stats(&portfolio_returns, years, periods_per_year),
+        benchmark: performance_stats(&benchmark_returns, years, periods_per_year),
+        periods,
+        average_turnover,
+    })
+}
+
+impl BacktestReport {
+    pub fn to_table(&self) -> String {
+        let mut table = format!(
+            "{:<10} {:<10} {:>8} {:>10} {:>10} {:>9}  {}\n",
+            "START", "END", "HOLDINGS", "RETURN %", "BENCH %", "TURNOVER", "SYMBOLS"
+        );
+        for period in &self.periods {
+            table += &format!(
+                "{:<10} {:<10} {:>8} {:>10.2} {:>10.2} {:>9.2}  {}\n",
+                period.start,
+                period.end,
+                period.holdings.len(),
+                100.0 * period.portfolio_return,
+                100.0 * period.benchmark_return,
+                period.turnover,
+                period.holdings.join(" "),
+            );
+        }
+        table += &format!(
+            "\n{:<10} {:>10} {:>8} {:>12} {:>14}\n",
+            "", "TOTAL %", "CAGR %", "VOLATILITY %", "MAX DRAWDOWN %"
+        );
+        for (name, stats) in [
+            ("Strategy", &self.strategy),
+            (self.config.benchmark.as_str(), &self.benchmark),
+        ] {
+            table += &format!(
+                "{:<10} {:>10.2} {:>8.2} {:>12.2} {:>14.2}\n",
+                name,
+                100.0 * stats.total_return,
+                100.0 * stats.cagr,
+                100.0 * stats.volatility,
+                100.0 * stats.max_drawdown,
+            );
+        }
+        table += &format!(
+            "\nAverage turnover per rebalancing: {:.1}%\n",
+            100.0 * self.average_turnover
+        );
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamentals_history::{BalanceSheet, ReportedFiling, ReportedStatements};
+    use crate::test_fixtures::{analysis_config, financials, information};
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn fundamentals(symbol: &str, pe_ratio: f64, price: f64) -> PointInTimeFundamentals {
+        PointInTimeFundamentals {
+            financials: financials(pe_ratio),
+            information: information(symbol),
+            price,
+        }
+    }
+
+    fn test_config(rebalance: RebalanceFrequency) -> BacktestConfig {
+        BacktestConfig {
+            start: date(2020, 1, 1),
+            end: date(2021, 1, 1),
+            rebalance,
+            benchmark: "INDEX".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_rebalance_dates() {
+        let dates = rebalance_dates(&test_config(RebalanceFrequency::Quarterly));
+        assert_eq!(
+            dates,
+            vec![
+                date(2020, 1, 1),
+                date(2020, 4, 1),
+                date(2020, 7, 1),
+                date(2020, 10, 1),
+                date(2021, 1, 1)
+            ]
+        );
+        assert_eq!(
+            rebalance_dates(&test_config(RebalanceFrequency::Yearly)).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_point_in_time_data() {
+        let mut data = HistoricalDataSet::new();
+        data.add_fundamentals("AAA", date(2020, 3, 1), fundamentals("AAA", 10.0, 20.0));
+        data.add_fundamentals("AAA", date(2020, 9, 1), fundamentals("AAA", 30.0, 20.0));
+        data.add_price("AAA", date(2020, 3, 1), 20.0);
+
+        assert!(data.fundamentals_as_of("AAA", date(2020, 2, 28)).is_none());
+        let known = data.fundamentals_as_of("AAA", date(2020, 8, 31)).unwrap();
+        assert_eq!(known.financials.pe_ratio, Some(10.0));
+        assert_eq!(data.price_on("AAA", date(2020, 12, 31)), Some(20.0));
+
+        // P/E 10 at 20 is P/E 25 at 50, above the limit of 22.5
+        let (financials, information) = reprice(&known, 50.0);
+        assert_eq!(financials.pe_ratio, Some(25.0));
+        assert_eq!(information.market_cap, 75000.0);
+        let universe = vec!["AAA".to_string()];
+        assert_eq!(
+            select_stocks(&data, &universe, date(2020, 4, 1), &analysis_config()).len(),
+            1
+        );
+        data.add_price("AAA", date(2020, 4, 1), 50.0);
+        assert!(select_stocks(&data, &universe, date(2020, 4, 1), &analysis_config()).is_empty());
+
+        // A balance sheet filed in May is not known in April
+        let filings = FundamentalsHistory {
+            filings: vec![ReportedFiling {
+                form: "10-K".to_string(),
+                year: 2020,
+                quarter: 0,
+                end_date: date(2020, 3, 31),
+                filed_date: date(2020, 5, 15),
+                accepted_at: None,
+                balance_sheet: BalanceSheet {
+                    total_current_assets: Some(1000.0),
+                    total_current_liabilities: Some(900.0),
+                    total_current_long_term_debt: None,
+                    total_long_term_debt: None,
+                },
+                statements: ReportedStatements {
+                    year: 2020,
+                    net_income: Some(10.0),
+                    ..ReportedStatements::default()
+                },
+            }],
+        };
+        data.add_filings("AAA", filings);
+        let april = data.fundamentals_as_of("AAA", date(2020, 4, 30)).unwrap();
+        assert_eq!(april.financials.total_current_assets, Some(50000.0));
+        let june = data.fundamentals_as_of("AAA", date(2020, 6, 1)).unwrap();
+        assert_eq!(june.financials.total_current_assets, Some(1000.0));
+        // Items the filing does not report are kept from the snapshot
+        assert_eq!(june.financials.total_current_long_term_debt, Some(500.0));
+        assert_eq!(june.financials.total_long_term_debt, Some(8000.0));
+        assert!(april.financials.reported_statements.is_empty());
+        assert_eq!(june.financials.reported_statements[0].year, 2020);
+    }
+
+    #[test]
+    fn test_backtest() -> Result<()> {
+        let mut data = HistoricalDataSet::new();
+        // CHEAP passes throughout, DEAR only in the second half
+        data.add_fundamentals(
+            "CHEAP",
+            date(2019, 12, 1),
+            fundamentals("CHEAP", 10.0, 20.0),
+        );
+        data.add_fundamentals("DEAR", date(2019, 12, 1), fundamentals("DEAR", 30.0, 20.0));
+        data.add_fundamentals("DEAR", date(2020, 6, 1), fundamentals("DEAR", 15.0, 20.0));
+        for (symbol, prices) in [
+            ("CHEAP", [20.0, 22.0, 11.0]),
+            ("DEAR", [20.0, 20.0, 30.0]),
+            ("INDEX", [100.0, 110.0, 121.0]),
+        ] {
+            for (price_date, price) in [date(2020, 1, 1), date(2020, 7, 1), date(2021, 1, 1)]
+                .into_iter()
+                .zip(prices)
+            {
+                data.add_price(symbol, price_date, price);
+            }
+        }
+
+        let universe = data.symbols();
+        let config = test_config(RebalanceFrequency::Quarterly);
+        let report = run_backtest(&data, &universe, &analysis_config(), &config)?;
+        assert_eq!(report.periods.len(), 4);
+        assert_eq!(report.periods[0].holdings, vec!["CHEAP"]);
+        assert_eq!(report.periods[0].turnover, 1.0); // Bought from cash
+        assert_eq!(report.periods[2].holdings, vec!["CHEAP", "DEAR"]);
+        assert_eq!(report.periods[2].turnover, 0.5);
+
+        // CHEAP +10% in the first half, then half in CHEAP -50% and half in DEAR +50%
+        assert!((report.strategy.total_return - 0.1).abs() < 1e-9);
+        assert!((report.benchmark.total_return - 0.21).abs() < 1e-9);
+        assert_eq!(report.strategy.max_drawdown, 0.0);
+        assert_eq!(report.average_turnover, 0.5 / 3.0);
+        assert!(report.to_table().contains("INDEX"));
+
+        let stats = performance_stats(&[0.5, -0.5, 0.5], 1.0, 4.0);
+        assert!((stats.max_drawdown - 0.5).abs() < 1e-9);
+        assert!((stats.total_return - 0.125).abs() < 1e-9);
+
+        // CHEAP going to zero wipes out the portfolio of the first half
+        data.add_price("CHEAP", date(2020, 7, 1), 0.0);
+        let report = run_backtest(&data, &universe, &analysis_config(), &config)?;
+        assert_eq!(report.periods[1].portfolio_return, -1.0);
+        assert!(report
+            .periods
+            .iter()
+            .all(|period| period.portfolio_return.is_finite() && period.turnover.is_finite()));
+        assert_eq!(report.strategy.total_return, -1.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_backtest_without_history() {
+        let mut data = HistoricalDataSet::new();
+        let config = test_config(RebalanceFrequency::Quarterly);
+        let error = run_backtest(&data, &[], &analysis_config(), &config).unwrap_err();
+        assert!(error.to_string().starts_with("No stocks to backtest"));
+
+        // The first run was stored after the start of the backtest
+        data.add_fundamentals("AAA", date(2020, 2, 1), fundamentals("AAA", 10.0, 20.0));
+        data.add_price("INDEX", date(2020, 1, 1), 100.0);
+        let error = run_backtest(&data, &data.symbols(), &analysis_config(), &config).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No fundamentals known on 2020-01-01, the history starts with the first stored run"
+        );
+    }
+}
@@ -0,0 +1,229 @@
+use crate::fundamentals_history::ReportedStatements;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BeneishConfig {
+    // M-scores above it flag a likely earnings manipulator
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    // Whether flagged stocks fail, otherwise the flag is only reported
+    #[serde(default)]
+    pub exclude_flagged: bool,
+}
+
+fn default_threshold() -> f64 {
+    -1.78
+}
+
+impl Default for BeneishConfig {
+    fn default() -> Self {
+        Self {
+            threshold: default_threshold(),
+            exclude_flagged: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BeneishIndices {
+    pub days_sales_in_receivables: f64, // DSRI
+    pub gross_margin: f64,              // GMI
+    pub asset_quality: f64,             // AQI
+    pub sales_growth: f64,              // SGI
+    pub depreciation: f64,              // DEPI
+    pub sga_expenses: f64,              // SGAI
+    pub leverage: f64,                  // LVGI
+    pub total_accruals: f64,            // TATA, accruals over total assets
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BeneishMScore {
+    pub year: i32,
+    pub m_score: f64,
+    pub flagged: bool, // Manipulation risk, the M-score is above the threshold
+    pub indices: BeneishIndices,
+    pub neutral_indices: Vec<String>, // Indices set to 1 for lack of reported items
+}
+
+fn ratio(numerator: Option<f64>, denominator: Option<f64>) -> Option<f64> {
+    let (numerator, denominator) = (numerator?, denominator?);
+    (denominator != 0.0).then(|| numerator / denominator)
+}
+
+fn receivables_to_sales(statements: &ReportedStatements) -> Option<f64> {
+    ratio(statements.receivables, statements.revenue)
+}
+
+fn gross_margin(statements: &ReportedStatements) -> Option<f64> {
+    ratio(statements.gross_profit, statements.revenue)
+}
+
+// Share of the assets other than current assets and property, plant and equipment
+fn asset_quality(statements: &ReportedStatements) -> Option<f64> {
+    let hard_assets = statements.total_current_assets? + statements.property_plant_equipment?;
+    Some(1.0 - ratio(Some(hard_assets), statements.total_assets)?)
+}
+
+fn depreciation_rate(statements: &ReportedStatements) -> Option<f64> {
+    let depreciation = statements.depreciation?;
+    ratio(
+        Some(depreciation),
+        Some(depreciation + statements.property_plant_equipment?),
+    )
+}
+
+fn sga_to_sales(statements: &ReportedStatements) -> Option<f64> {
+    ratio(statements.sga_expense, statements.revenue)
+}
+
+// Missing long term debt is taken as no long term debt
+fn leverage(statements: &ReportedStatements) -> Option<f64> {
+    let debt = statements.total_current_liabilities? + statements.long_term_debt.unwrap_or(0.0);
+    ratio(Some(debt), statements.total_assets)
+}
+
+// Beneish's eight variable model over the latest year and the year before, the statements are
+// ordered newest first
+pub fn beneish_m_score(
+    statements: &[ReportedStatements],
+    beneish_config: &BeneishConfig,
+) -> Result<BeneishMScore> {
+    if statements.len() < 2 {
+        return Err(Error::msg(
+            "Less than two years of reported statements for the Beneish M-score",
+        ));
+    }
+    let (current, prior) = (&statements[0], &statements[1]);
+    let sales_growth = ratio(current.revenue, prior.revenue)
+        .ok_or_else(|| Error::msg("No revenue for the Beneish M-score"))?;
+    let total_accruals = ratio(
+        current
+            .net_income
+            .zip(current.operating_cash_flow)
+            .map(|(net_income, cash_flow)| net_income - cash_flow),
+        current.total_assets,
+    )
+    .ok_or_else(|| {
+        Error::msg("No net income, operating cash flow or total assets for the Beneish M-score")
+    })?;
+
+    let mut neutral_indices = Vec::new();
+    let mut index = |name: &str, value: Option<f64>| match value {
+        Some(value) if value.is_finite() => value,
+        _ => {
+            neutral_indices.push(name.to_string());
+            1.0
+        }
+    };
+    let indices = BeneishIndices {
+        days_sales_in_receivables: index(
+            "days_sales_in_receivables",
+            ratio(receivables_to_sales(current), receivables_to_sales(prior)),
+        ),
+        gross_margin: index(
+            "gross_margin",
+            ratio(gross_margin(prior), gross_margin(current)),
+        ),
+        asset_quality: index(
+            "asset_quality",
+            ratio(asset_quality(current), asset_quality(prior)),
+        ),
+        sales_growth,
+        depreciation: index(
+            "depreciation",
+            ratio(depreciation_rate(prior), depreciation_rate(current)),
+        ),
+        sga_expenses: index(
+            "sga_expenses",
+            ratio(sga_to_sales(current), sga_to_sales(prior)),
+        ),
+        leverage: index("leverage", ratio(leverage(current), leverage(prior))),
+        total_accruals,
+    };
+
+    let m_score = -4.84
+        + 0.92 * indices.days_sales_in_receivables
+        + 0.528 * indices.gross_margin
+        + 0.404 * indices.asset_quality
+        + 0.892 * indices.sales_growth
+        + 0.115 * indices.depreciation
+        - 0.172 * indices.sga_expenses
+        + 4.679 * indices.total_accruals
+        - 0.327 * indices.leverage;
+    Ok(BeneishMScore {
+        year: current.year,
+        m_score,
+        flagged: m_score > beneish_config.threshold,
+        indices,
+        neutral_indices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statements(
+        year: i32,
+        revenue: f64,
+        receivables: f64,
+        net_income: f64,
+    ) -> ReportedStatements {
+        ReportedStatements {
+            year,
+            total_assets: Some(1000.0),
+            total_current_assets: Some(400.0),
+            total_current_liabilities: Some(200.0),
+            long_term_debt: Some(100.0),
+            receivables: Some(receivables),
+            property_plant_equipment: Some(300.0),
+            revenue: Some(revenue),
+            gross_profit: Some(0.4 * revenue),
+            sga_expense: Some(0.1 * revenue),
+            depreciation: Some(30.0),
+            net_income: Some(net_income),
+            operating_cash_flow: Some(60.0),
+            ..ReportedStatements::default()
+        }
+    }
+
+    #[test]
+    fn test_beneish_m_score() -> Result<()> {
+        let beneish_config = BeneishConfig::default();
+
+        // Steady company: every index 1 and accruals of -0.01
+        let steady = beneish_m_score(
+            &[
+                statements(2022, 1000.0, 100.0, 50.0),
+                statements(2021, 1000.0, 100.0, 50.0),
+            ],
+            &beneish_config,
+        )?;
+        assert_eq!(steady.year, 2022);
+        assert!((steady.m_score - (-2.52679)).abs() < 1e-9);
+        assert!(!steady.flagged);
+        assert!(steady.neutral_indices.is_empty());
+
+        // Receivables growing much faster than sales and earnings well above the cash flow
+        let mut current = statements(2022, 1300.0, 260.0, 160.0);
+        current.sga_expense = None;
+        let suspicious = beneish_m_score(
+            &[current, statements(2021, 1000.0, 100.0, 50.0)],
+            &beneish_config,
+        )?;
+        assert!((suspicious.indices.days_sales_in_receivables - 2.0).abs() < 1e-9);
+        assert!((suspicious.indices.total_accruals - 0.1).abs() < 1e-9);
+        assert!(suspicious.flagged);
+        assert_eq!(suspicious.neutral_indices, vec!["sga_expenses"]);
+
+        let mut no_cash_flow = statements(2022, 1000.0, 100.0, 50.0);
+        no_cash_flow.operating_cash_flow = None;
+        assert!(beneish_m_score(
+            &[no_cash_flow, statements(2021, 1000.0, 100.0, 50.0)],
+            &beneish_config
+        )
+        .is_err());
+        Ok(())
+    }
+}
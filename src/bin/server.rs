@@ -0,0 +1,37 @@
+use anyhow::Result;
+use financial_analysis::financial_analysis::StockAnalyzer;
+use financial_analysis::server::{router, AppState, ServerConfig};
+use financial_analysis::settings::load_settings;
+use financial_analysis::storage::{Storage, StorageConfig};
+use std::net::SocketAddr;
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "financial-analysis-server")]
+struct Opt {
+    /// Path to the settings file
+    settings_filename: String,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let opt = Opt::from_args();
+
+    let settings = load_settings(&opt.settings_filename)?;
+    let server_config = ServerConfig::from_json_value(settings["server"].clone());
+    let storage_config = StorageConfig::from_json_value(settings["storage"].clone());
+    let storage = Storage::open(&storage_config.database_path)?;
+    let stock_analyzer = StockAnalyzer::new(&opt.settings_filename);
+
+    let app = router(AppState::new(stock_analyzer, storage, &server_config));
+    let address: SocketAddr = server_config.bind_address.parse()?;
+    println!("Listening on {address}");
+    axum::Server::bind(&address)
+        .serve(app.into_make_service())
+        .with_graceful_shutdown(async {
+            tokio::signal::ctrl_c().await.ok();
+        })
+        .await?;
+
+    Ok(())
+}
@@ -0,0 +1,362 @@
+use crate::alerts::{send_run_alerts, AlertConfig};
+use crate::financial_analysis::{AnalysisConfig, StockAnalyzer};
+use crate::scan::{scan_exchange, scan_exchange_with, Screen};
+use crate::settings::load_settings;
+use crate::stock_data_fetching::DataFetchConfig;
+use crate::storage::{unix_timestamp, JobState, Storage};
+use crate::watchlist::{check_watchlist, refresh_watchlist_quotes};
+use anyhow::{Error, Result};
+use chrono::{DateTime, TimeZone, Utc};
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time::sleep;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    // Screens every stock on the exchange
+    FullScan,
+    // Screens every stock on the exchange for net-nets
+    NetNetScan,
+    // Screens the watched symbols with freshly fetched fundamentals
+    WatchlistCheck,
+    // Re-evaluates the watched symbols with fresh quotes and the stored fundamentals
+    WatchlistQuotes,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JobConfig {
+    pub name: String, // Key of the persisted job state
+    pub kind: JobKind,
+    pub schedule: String, // Cron expression with a seconds field, evaluated in UTC
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DaemonConfig {
+    #[serde(default = "default_exchange")]
+    pub exchange: String, // Exchange screened by full scans
+    #[serde(default)]
+    pub jobs: Vec<JobConfig>,
+}
+
+fn default_exchange() -> String {
+    "US".to_string()
+}
+
+pub struct ScheduledJob {
+    pub config: JobConfig,
+    pub schedule: Schedule,
+}
+
+// Everything the daemon reads from the settings file. Parsed up front so that a broken file is
+// rejected as a whole on reload instead of panicking halfway.
+pub struct DaemonSettings {
+    pub data_fetch_config: DataFetchConfig,
+    pub analysis_config: AnalysisConfig,
+    pub alert_config: AlertConfig,
+    pub exchange: String,
+    pub jobs: Vec<ScheduledJob>,
+}
+
+impl DaemonSettings {
+    pub fn from_json_value(settings: &serde_json::Value) -> Result<Self> {
+        let data_fetch_config: DataFetchConfig =
+            serde_json::from_value(settings["data_fetching"].clone())?;
+        let analysis_config = serde_json::from_value(settings["analysis"].clone())?;
+        let alert_config = match &settings["alerts"] {
+            serde_json::Value::Null => AlertConfig::default(),
+            alerts => serde_json::from_value(alerts.clone())?,
+        };
+        let daemon_config: DaemonConfig = serde_json::from_value(settings["daemon"].clone())
+            .map_err(|error| Error::msg(format!("Invalid daemon settings: {error}")))?;
+
+        let mut jobs: Vec<ScheduledJob> = Vec::new();
+        for job in daemon_config.jobs {
+            if jobs.iter().any(|other| other.config.name == job.name) {
+                return Err(Error::msg(format!("Duplicate job name {}", job.name)));
+            }
+            let schedule = Schedule::from_str(&job.schedule).map_err(|error| {
+                Error::msg(format!("Invalid schedule of job {}: {}", job.name, error))
+            })?;
+            jobs.push(ScheduledJob {
+                config: job,
+                schedule,
+            });
+        }
+        if jobs.is_empty() {
+            return Err(Error::msg("No jobs configured in daemon.jobs"));
+        }
+
+        Ok(Self {
+            data_fetch_config,
+            analysis_config,
+            alert_config,
+            exchange: daemon_config.exchange,
+            jobs,
+        })
+    }
+
+    pub fn from_file(filename: &str) -> Result<Self> {
+        Self::from_json_value(&load_settings(filename)?)
+    }
+}
+
+// Time at which the job is due next. A scheduled time that was missed since the last run, e.g.
+// while the daemon was not running, makes the job due immediately.
+pub fn next_due(
+    schedule: &Schedule,
+    last_run_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    match last_run_at {
+        Some(last_run_at) => schedule.after(&last_run_at).next().map(|due| due.max(now)),
+        None => schedule.after(&now).next(),
+    }
+}
+
+pub struct Daemon {
+    settings_filename: String,
+    analyzer: StockAnalyzer, // Kept between runs so the API call count carries over
+    storage: Storage,
+    alert_config: AlertConfig,
+    exchange: String,
+    jobs: Vec<ScheduledJob>,
+}
+
+impl Daemon {
+    pub fn new(settings_filename: &str, storage: Storage) -> Result<Self> {
+        let settings = DaemonSettings::from_file(settings_filename)?;
+        Ok(Self {
+            settings_filename: settings_filename.to_string(),
+            analyzer: StockAnalyzer::with_configs(
+                settings.data_fetch_config,
+                settings.analysis_config,
+            ),
+            storage,
+            alert_config: settings.alert_config,
+            exchange: settings.exchange,
+            jobs: settings.jobs,
+        })
+    }
+
+    // Keeps the previous settings when the file can not be parsed
+    fn reload_settings(&mut self) {
+        match DaemonSettings::from_file(&self.settings_filename) {
+            Ok(settings) => {
+                self.analyzer.data_fetch_config = settings.data_fetch_config;
+                self.analyzer.analysis_config = settings.analysis_config;
+                self.alert_config = settings.alert_config;
+                self.exchange = settings.exchange;
+                self.jobs = settings.jobs;
+                println!("Reloaded settings from {}", self.settings_filename);
+            }
+            Err(error) => println!(
+                "Could not reload settings from {}, keeping the previous ones: {}",
+                self.settings_filename, error
+            ),
+        }
+    }
+
+    // Index and due time of the job that is due first
+    fn next_job(&self) -> Result<(usize, DateTime<Utc>)> {
+        let now = Utc::now();
+        let mut next: Option<(usize, DateTime<Utc>)> = None;
+        for (index, job) in self.jobs.iter().enumerate() {
+            let last_run_at = self
+                .storage
+                .job_state(&job.config.name)?
+                .and_then(|state| Utc.timestamp_opt(state.last_run_at, 0).single());
+            let due = match next_due(&job.schedule, last_run_at, now) {
+                Some(due) => due,
+                None => continue,
+            };
+            if next.is_none_or(|(_, next_due)| due < next_due) {
+                next = Some((index, due));
+            }
+        }
+        next.ok_or_else(|| Error::msg("No job has an upcoming scheduled time"))
+    }
+
+    // Returns the id of the stored run
+    async fn run_job(&mut self, kind: JobKind) -> Result<i64> {
+        let run_id = match kind {
+            JobKind::FullScan => {
+                let result =
+                    scan_exchange(&mut self.analyzer, &mut self.storage, &self.exchange).await?;
+                println!("Worthy stocks: {}", result.worthy_stocks.len());
+                result.run_id
+            }
+            JobKind::NetNetScan => {
+                let result = scan_exchange_with(
+                    &mut self.analyzer,
+                    &mut self.storage,
+                    &self.exchange,
+                    Screen::NetNet,
+                )
+                .await?;
+                println!("Net-nets: {}", result.worthy_stocks.len());
+                result.run_id
+            }
+            JobKind::WatchlistCheck => {
+                let check = check_watchlist(&mut self.analyzer, &mut self.storage).await?;
+                print!("{}", check.to_table());
+                check.run_id
+            }
+            JobKind::WatchlistQuotes => {
+                refresh_watchlist_quotes(&mut self.analyzer, &mut self.storage).await?
+            }
+        };
+        send_run_alerts(&self.alert_config, &self.storage, run_id).await?;
+        Ok(run_id)
+    }
+
+    // Runs the scheduled jobs until SIGTERM or SIGINT. A job that is running when the signal
+    // arrives is interrupted, its run keeps the reports stored so far and is left unfinished.
+    // SIGHUP reloads the settings, after the running job if there is one.
+    pub async fn run(&mut self) -> Result<()> {
+        let mut hangup = signal(SignalKind::hangup())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        println!("Daemon started with {} jobs", self.jobs.len());
+
+        loop {
+            let (index, due) = self.next_job()?;
+            let job = self.jobs[index].config.clone();
+            println!("Next job: {} at {}", job.name, due);
+
+            let wait = (due - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                _ = sleep(wait) => {}
+                _ = hangup.recv() => {
+                    self.reload_settings();
+                    continue;
+                }
+                _ = terminate.recv() => break,
+                _ = interrupt.recv() => break,
+            }
+
+            println!("Starting job {}", job.name);
+            let mut reload_pending = false;
+            let result = {
+                let run_job = self.run_job(job.kind);
+                tokio::pin!(run_job);
+                loop {
+                    tokio::select! {
+                        result = &mut run_job => break Some(result),
+                        _ = hangup.recv() => {
+                            println!("Reloading settings once job {} has finished", job.name);
+                            reload_pending = true;
+                        }
+                        _ = terminate.recv() => break None,
+                        _ = interrupt.recv() => break None,
+                    }
+                }
+            };
+            let result = match result {
+                Some(result) => result,
+                None => {
+                    println!("Interrupted job {}", job.name);
+                    break;
+                }
+            };
+
+            let state = match result {
+                Ok(run_id) => {
+                    println!("Finished job {}, saved run {}", job.name, run_id);
+                    JobState {
+                        name: job.name.clone(),
+                        last_run_at: unix_timestamp(),
+                        last_run_id: Some(run_id),
+                        last_error: None,
+                    }
+                }
+                Err(error) => {
+                    println!("Job {} failed: {}", job.name, error);
+                    JobState {
+                        name: job.name.clone(),
+                        last_run_at: unix_timestamp(),
+                        last_run_id: None,
+                        last_error: Some(error.to_string()),
+                    }
+                }
+            };
+            self.storage.save_job_state(&state)?;
+
+            if reload_pending {
+                self.reload_settings();
+            }
+        }
+        println!("Daemon stopped");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn settings(jobs: serde_json::Value) -> serde_json::Value {
+        let mut settings = load_settings("config/example.json").unwrap();
+        settings["daemon"] = json!({ "jobs": jobs });
+        settings
+    }
+
+    #[test]
+    fn test_daemon_settings() {
+        let daemon_settings = DaemonSettings::from_file("config/example.json").unwrap();
+        assert_eq!(daemon_settings.exchange, "US");
+        assert!(daemon_settings
+            .jobs
+            .iter()
+            .any(|job| job.config.kind == JobKind::WatchlistQuotes));
+
+        let invalid_schedule = settings(json!([
+            { "name": "scan", "kind": "full_scan", "schedule": "every sunday" }
+        ]));
+        assert!(DaemonSettings::from_json_value(&invalid_schedule).is_err());
+
+        let duplicate_names = settings(json!([
+            { "name": "scan", "kind": "full_scan", "schedule": "0 0 2 * * Sun" },
+            { "name": "scan", "kind": "watchlist_check", "schedule": "0 0 3 * * *" }
+        ]));
+        assert!(DaemonSettings::from_json_value(&duplicate_names).is_err());
+        assert!(DaemonSettings::from_json_value(&settings(json!([]))).is_err());
+    }
+
+    #[test]
+    fn test_next_due() {
+        let hourly = Schedule::from_str("0 0 * * * *").unwrap();
+        let now = Utc.with_ymd_and_hms(2023, 2, 6, 14, 20, 0).unwrap();
+        let next_hour = Utc.with_ymd_and_hms(2023, 2, 6, 15, 0, 0).unwrap();
+
+        assert_eq!(next_due(&hourly, None, now), Some(next_hour));
+        let recent_run = Utc.with_ymd_and_hms(2023, 2, 6, 14, 0, 5).unwrap();
+        assert_eq!(next_due(&hourly, Some(recent_run), now), Some(next_hour));
+        // The 13:00 and 14:00 runs were missed
+        let missed_run = Utc.with_ymd_and_hms(2023, 2, 6, 12, 30, 0).unwrap();
+        assert_eq!(next_due(&hourly, Some(missed_run), now), Some(now));
+    }
+
+    #[test]
+    fn test_job_state_storage() -> Result<()> {
+        let storage = Storage::open_in_memory()?;
+        assert!(storage.job_state("scan")?.is_none());
+
+        let mut state = JobState {
+            name: "scan".to_string(),
+            last_run_at: 1675692000,
+            last_run_id: Some(3),
+            last_error: None,
+        };
+        storage.save_job_state(&state)?;
+        state.last_error = Some("No stock list".to_string());
+        state.last_run_id = None;
+        storage.save_job_state(&state)?;
+        assert_eq!(storage.job_state("scan")?, Some(state));
+        Ok(())
+    }
+}
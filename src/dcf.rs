@@ -0,0 +1,414 @@
+use crate::financial_analysis::{aaa_bond_yield, margin_of_safety, AnalysisConfig};
+use crate::stock_data_fetching::{CompanyFinancials, CompanyInformation, CompanyQuote};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DcfConfig {
+    // Added to the AAA bond yield of the company's country to get the discount rate
+    #[serde(default = "default_equity_risk_premium")]
+    pub equity_risk_premium: f64, // In percent / 100
+    #[serde(default = "default_scenarios")]
+    pub scenarios: Vec<DcfScenario>,
+    // Minimum margin of safety of the base scenario value to the current price
+    #[serde(default = "default_margin_of_safety_min")]
+    pub margin_of_safety_min: f64, // In percent / 100
+    // Whether a lower margin of safety fails the stock, otherwise it is only reported
+    #[serde(default)]
+    pub required: bool,
+    #[serde(default)]
+    pub reverse: ReverseDcfConfig,
+}
+
+// The reverse DCF solves for the constant growth over the projected years that justifies the price
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReverseDcfConfig {
+    #[serde(default = "default_projection_years")]
+    pub projection_years: usize,
+    #[serde(default = "default_terminal_growth")]
+    pub terminal_growth: f64, // In percent / 100
+}
+
+fn default_projection_years() -> usize {
+    10
+}
+
+fn default_terminal_growth() -> f64 {
+    0.025
+}
+
+impl Default for ReverseDcfConfig {
+    fn default() -> Self {
+        Self {
+            projection_years: default_projection_years(),
+            terminal_growth: default_terminal_growth(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DcfScenario {
+    pub name: String, // The scenario named "base" decides the criterion
+    // Free cash flow growth of every projected year, in percent / 100
+    pub growth_path: Vec<f64>,
+    // Growth after the projected years, it has to stay below the discount rate
+    pub terminal_growth: f64, // In percent / 100
+}
+
+fn default_equity_risk_premium() -> f64 {
+    0.05
+}
+
+fn default_margin_of_safety_min() -> f64 {
+    0.25
+}
+
+// Ten projected years, growth fading to the terminal growth in the second half
+fn default_scenarios() -> Vec<DcfScenario> {
+    [
+        ("bear", 0.02, 0.01, 0.015),
+        ("base", 0.06, 0.04, 0.025),
+        ("bull", 0.1, 0.06, 0.03),
+    ]
+    .iter()
+    .map(
+        |&(name, first_growth, second_growth, terminal_growth)| DcfScenario {
+            name: name.to_string(),
+            growth_path: [[first_growth; 5], [second_growth; 5]].concat(),
+            terminal_growth,
+        },
+    )
+    .collect()
+}
+
+impl Default for DcfConfig {
+    fn default() -> Self {
+        Self {
+            equity_risk_premium: default_equity_risk_premium(),
+            scenarios: default_scenarios(),
+            margin_of_safety_min: default_margin_of_safety_min(),
+            required: false,
+            reverse: ReverseDcfConfig::default(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DcfScenarioValue {
+    pub name: String,
+    pub value_per_share: f64,
+    // To the current price, negative when overpriced and None without a positive value
+    pub margin_of_safety: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DcfValuation {
+    pub year: i32,           // Of the latest reported cash flow statement
+    pub free_cash_flow: f64, // Projected from, in million USD
+    pub discount_rate: f64,  // In percent / 100
+    pub scenarios: Vec<DcfScenarioValue>,
+}
+
+impl DcfValuation {
+    pub fn scenario(&self, name: &str) -> Option<&DcfScenarioValue> {
+        self.scenarios.iter().find(|scenario| scenario.name == name)
+    }
+}
+
+// Present value of the projected free cash flows and of the Gordon growth terminal value after the
+// last projected year
+pub fn discounted_value(
+    free_cash_flow: f64,
+    scenario: &DcfScenario,
+    discount_rate: f64,
+) -> Result<f64> {
+    if scenario.growth_path.is_empty() {
+        return Err(Error::msg(format!(
+            "No growth path in the {} scenario",
+            scenario.name
+        )));
+    }
+    if scenario.terminal_growth >= discount_rate {
+        return Err(Error::msg(format!(
+            "Terminal growth of the {} scenario is not below the discount rate",
+            scenario.name
+        )));
+    }
+    let mut cash_flow = free_cash_flow;
+    let mut discount = 1.0;
+    let mut value = 0.0;
+    for growth in &scenario.growth_path {
+        cash_flow *= 1.0 + growth;
+        discount *= 1.0 + discount_rate;
+        value += cash_flow / discount;
+    }
+    let terminal_value =
+        cash_flow * (1.0 + scenario.terminal_growth) / (discount_rate - scenario.terminal_growth);
+    Ok(value + terminal_value / discount)
+}
+
+// Projects the average free cash flow (operating cash flow - capital expenditure) of the reported
+// years, so that a single exceptional year does not drive the valuation
+pub fn dcf_valuation(
+    financials: &CompanyFinancials,
+    information: &CompanyInformation,
+    quote: &CompanyQuote,
+    analysis_config: &AnalysisConfig,
+) -> Result<DcfValuation> {
+    let free_cash_flows: Vec<f64> = financials
+        .reported_statements
+        .iter()
+        .filter_map(|statements| statements.free_cash_flow())
+        .collect();
+    let latest = financials
+        .reported_statements
+        .iter()
+        .find(|statements| statements.free_cash_flow().is_some())
+        .ok_or_else(|| Error::msg("No reported cash flow statements for the DCF valuation"))?;
+    let free_cash_flow = free_cash_flows.iter().sum::<f64>() / free_cash_flows.len() as f64;
+    if free_cash_flow <= 0.0 {
+        return Err(Error::msg("No positive free cash flow to project"));
+    }
+    if information.shares_outstanding <= 0.0 {
+        return Err(Error::msg("No shares outstanding"));
+    }
+
+    let discount_rate =
+        aaa_bond_yield(information, analysis_config) + analysis_config.dcf.equity_risk_premium;
+    let scenarios = analysis_config
+        .dcf
+        .scenarios
+        .iter()
+        .map(|scenario| {
+            let value_per_share = discounted_value(free_cash_flow, scenario, discount_rate)?
+                / information.shares_outstanding;
+            Ok(DcfScenarioValue {
+                name: scenario.name.clone(),
+                value_per_share,
+                margin_of_safety: margin_of_safety(value_per_share, quote.c),
+            })
+        })
+        .collect::<Result<Vec<DcfScenarioValue>>>()?;
+    Ok(DcfValuation {
+        year: latest.year,
+        free_cash_flow,
+        discount_rate,
+        scenarios,
+    })
+}
+
+pub fn check_dcf_margin_of_safety(
+    valuation: &Result<DcfValuation>,
+    analysis_config: &AnalysisConfig,
+) -> Result<bool> {
+    let valuation = match valuation {
+        Ok(valuation) => valuation,
+        Err(error) => return Err(Error::msg(error.to_string())),
+    };
+    for scenario in &valuation.scenarios {
+        println!(
+            "DCF {} value per share: {:.2} | Margin of safety: {:?}",
+            scenario.name, scenario.value_per_share, scenario.margin_of_safety
+        );
+    }
+    let base = valuation
+        .scenario("base")
+        .ok_or_else(|| Error::msg("No base DCF scenario"))?;
+    Ok(base
+        .margin_of_safety
+        .is_some_and(|margin| margin >= analysis_config.dcf.margin_of_safety_min))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CashFlowSource {
+    FreeCashFlow, // Of the latest reported cash flow statement
+    Earnings,     // Trailing EPS, for companies without a positive free cash flow
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ImpliedGrowth {
+    pub source: CashFlowSource,
+    pub cash_flow_per_share: f64,
+    pub discount_rate: f64,             // In percent / 100
+    pub implied_growth: f64,            // In percent / 100
+    pub historical_growth: Option<f64>, // 5y earnings growth, in percent / 100
+    // Historical minus implied growth, large when the price assumes far less growth than delivered
+    pub growth_gap: Option<f64>,
+}
+
+// Growth rates the reverse DCF solves within
+const IMPLIED_GROWTH_RANGE: (f64, f64) = (-0.5, 1.0);
+
+// Bisection on the growth over the projected years, the discounted value rises with the growth
+pub fn implied_growth(
+    financials: &CompanyFinancials,
+    information: &CompanyInformation,
+    quote: &CompanyQuote,
+    analysis_config: &AnalysisConfig,
+) -> Result<ImpliedGrowth> {
+    let free_cash_flow_per_share = financials
+        .reported_statements
+        .iter()
+        .find_map(|statements| statements.free_cash_flow())
+        .filter(|_| information.shares_outstanding > 0.0)
+        .map(|free_cash_flow| free_cash_flow / information.shares_outstanding);
+    let (source, cash_flow_per_share) =
+        match (free_cash_flow_per_share, financials.earnings_per_share) {
+            (Some(free_cash_flow), _) if free_cash_flow > 0.0 => {
+                (CashFlowSource::FreeCashFlow, free_cash_flow)
+            }
+            (_, Some(earnings)) if earnings > 0.0 => (CashFlowSource::Earnings, earnings),
+            _ => {
+                return Err(Error::msg(
+                    "No positive free cash flow or earnings per share",
+                ))
+            }
+        };
+    if quote.c <= 0.0 {
+        return Err(Error::msg("No current price"));
+    }
+
+    let reverse_config = &analysis_config.dcf.reverse;
+    let discount_rate =
+        aaa_bond_yield(information, analysis_config) + analysis_config.dcf.equity_risk_premium;
+    let value = |growth: f64| {
+        let scenario = DcfScenario {
+            name: "reverse".to_string(),
+            growth_path: vec![growth; reverse_config.projection_years],
+            terminal_growth: reverse_config.terminal_growth,
+        };
+        discounted_value(cash_flow_per_share, &scenario, discount_rate)
+    };
+    let (mut low, mut high) = IMPLIED_GROWTH_RANGE;
+    if value(low)? > quote.c || value(high)? < quote.c {
+        return Err(Error::msg(format!(
+            "Implied growth outside {:.0}% to {:.0}%",
+            100.0 * low,
+            100.0 * high
+        )));
+    }
+    while high - low > 1e-7 {
+        let middle = (low + high) / 2.0;
+        if value(middle)? < quote.c {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+
+    let implied_growth = (low + high) / 2.0;
+    let historical_growth = financials
+        .earnings_growth_5_yr_avg
+        .map(|growth| growth / 100.0);
+    Ok(ImpliedGrowth {
+        source,
+        cash_flow_per_share,
+        discount_rate,
+        implied_growth,
+        historical_growth,
+        growth_gap: historical_growth.map(|historical| historical - implied_growth),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamentals_history::ReportedStatements;
+    use crate::test_fixtures::{analysis_config, financials, information, quote};
+
+    fn cash_flows(year: i32, operating_cash_flow: f64) -> ReportedStatements {
+        ReportedStatements {
+            year,
+            operating_cash_flow: Some(operating_cash_flow),
+            capital_expenditure: Some(20.0),
+            ..ReportedStatements::default()
+        }
+    }
+
+    #[test]
+    fn test_dcf_valuation() -> Result<()> {
+        // Free cash flows of 80, 100 and 120 average to 100, discounted at 3.36% + 6.64% = 10%
+        let mut financials = financials(8.0);
+        financials.reported_statements = vec![
+            cash_flows(2022, 140.0),
+            cash_flows(2021, 120.0),
+            cash_flows(2020, 100.0),
+        ];
+        let mut information = information("DCF");
+        information.shares_outstanding = 10.0;
+        let mut analysis_config = analysis_config();
+        analysis_config.dcf.equity_risk_premium = 0.0664;
+        analysis_config.dcf.scenarios = vec![
+            DcfScenario {
+                name: "base".to_string(),
+                growth_path: vec![0.0, 0.0],
+                terminal_growth: 0.0,
+            },
+            DcfScenario {
+                name: "bull".to_string(),
+                growth_path: vec![0.1],
+                terminal_growth: 0.0,
+            },
+        ];
+
+        let valuation = dcf_valuation(&financials, &information, &quote(80.0), &analysis_config)?;
+        assert_eq!(valuation.year, 2022);
+        assert_eq!(valuation.free_cash_flow, 100.0);
+        assert!((valuation.discount_rate - 0.1).abs() < 1e-12);
+        // Without growth the value is the perpetuity 100 / 10%
+        let base = valuation.scenario("base").unwrap();
+        assert!((base.value_per_share - 100.0).abs() < 1e-9);
+        assert!((base.margin_of_safety.unwrap() - 0.2).abs() < 1e-9);
+        // 110 / 1.1 + 1100 / 1.1
+        assert!((valuation.scenario("bull").unwrap().value_per_share - 110.0).abs() < 1e-9);
+        assert!(!check_dcf_margin_of_safety(
+            &Ok(valuation),
+            &analysis_config
+        )?);
+
+        // Terminal growth at the discount rate has no finite value
+        analysis_config.dcf.scenarios[1].terminal_growth = 0.1;
+        assert!(dcf_valuation(&financials, &information, &quote(80.0), &analysis_config).is_err());
+
+        analysis_config.dcf.scenarios[1].terminal_growth = 0.0;
+        financials.reported_statements.clear();
+        assert!(dcf_valuation(&financials, &information, &quote(80.0), &analysis_config).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_implied_growth() -> Result<()> {
+        let mut financials = financials(8.0);
+        financials.reported_statements = vec![cash_flows(2022, 120.0)];
+        let mut information = information("DCF");
+        information.shares_outstanding = 10.0;
+        let mut analysis_config = analysis_config();
+        analysis_config.dcf.equity_risk_premium = 0.0664;
+        analysis_config.dcf.reverse = ReverseDcfConfig {
+            projection_years: 5,
+            terminal_growth: 0.0,
+        };
+
+        // Free cash flow of 10 per share, a price of 100 is the perpetuity without growth
+        let implied = implied_growth(&financials, &information, &quote(100.0), &analysis_config)?;
+        assert_eq!(implied.source, CashFlowSource::FreeCashFlow);
+        assert_eq!(implied.cash_flow_per_share, 10.0);
+        assert!(implied.implied_growth.abs() < 1e-6);
+        assert_eq!(implied.historical_growth, Some(0.07));
+        assert!((implied.growth_gap.unwrap() - 0.07).abs() < 1e-6);
+
+        let implied = implied_growth(&financials, &information, &quote(150.0), &analysis_config)?;
+        assert!(implied.implied_growth > 0.07);
+        assert!(implied.growth_gap.unwrap() < 0.0);
+
+        // Without a positive free cash flow the EPS of 3 is projected
+        financials.reported_statements[0].operating_cash_flow = Some(10.0);
+        let implied = implied_growth(&financials, &information, &quote(30.0), &analysis_config)?;
+        assert_eq!(implied.source, CashFlowSource::Earnings);
+        assert!(implied.implied_growth.abs() < 1e-6);
+
+        assert!(implied_growth(&financials, &information, &quote(1e6), &analysis_config).is_err());
+        Ok(())
+    }
+}
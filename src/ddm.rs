@@ -0,0 +1,202 @@
+use crate::financial_analysis::{aaa_bond_yield, margin_of_safety, AnalysisConfig};
+use crate::stock_data_fetching::{CompanyFinancials, CompanyInformation, CompanyQuote};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DdmConfig {
+    // Added to the AAA bond yield of the company's country to get the required return
+    #[serde(default = "default_equity_risk_premium")]
+    pub equity_risk_premium: f64, // In percent / 100
+    // Years of the 5y dividend growth in the two-stage model, before the terminal growth
+    #[serde(default = "default_high_growth_years")]
+    pub high_growth_years: u32,
+    #[serde(default = "default_terminal_growth")]
+    pub terminal_growth: f64, // In percent / 100
+}
+
+fn default_equity_risk_premium() -> f64 {
+    0.05
+}
+
+fn default_high_growth_years() -> u32 {
+    5
+}
+
+fn default_terminal_growth() -> f64 {
+    0.025
+}
+
+impl Default for DdmConfig {
+    fn default() -> Self {
+        Self {
+            equity_risk_premium: default_equity_risk_premium(),
+            high_growth_years: default_high_growth_years(),
+            terminal_growth: default_terminal_growth(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DdmValuation {
+    pub dividend_per_share: f64,
+    pub growth: f64,          // 5y dividend growth, in percent / 100
+    pub required_return: f64, // In percent / 100
+    // None when the 5y growth is not below the required return, with the reason in gordon_error
+    pub gordon_value: Option<f64>,
+    #[serde(default)]
+    pub gordon_error: Option<String>,
+    pub two_stage_value: f64, // The fair value per share
+    // Return implied by the current price, next year's dividend yield plus the growth
+    pub implied_yield: f64,
+    pub margin_of_safety: Option<f64>, // Of the two-stage value to the current price
+}
+
+// V = D1 / (r - g), only defined for a growth below the required return
+pub fn gordon_growth_value(
+    dividend_per_share: f64,
+    growth: f64,
+    required_return: f64,
+) -> Result<f64> {
+    if growth >= required_return {
+        return Err(Error::msg(format!(
+            "Dividend growth of {:.2}% is not below the required return of {:.2}%",
+            100.0 * growth,
+            100.0 * required_return
+        )));
+    }
+    Ok(dividend_per_share * (1.0 + growth) / (required_return - growth))
+}
+
+// The dividends grow at the high growth for the first years and then at the terminal growth
+pub fn two_stage_value(
+    dividend_per_share: f64,
+    high_growth: f64,
+    high_growth_years: u32,
+    terminal_growth: f64,
+    required_return: f64,
+) -> Result<f64> {
+    let mut dividend = dividend_per_share;
+    let mut discount = 1.0;
+    let mut value = 0.0;
+    for _ in 0..high_growth_years {
+        dividend *= 1.0 + high_growth;
+        discount *= 1.0 + required_return;
+        value += dividend / discount;
+    }
+    Ok(value + gordon_growth_value(dividend, terminal_growth, required_return)? / discount)
+}
+
+pub fn ddm_valuation(
+    financials: &CompanyFinancials,
+    information: &CompanyInformation,
+    quote: &CompanyQuote,
+    analysis_config: &AnalysisConfig,
+) -> Result<DdmValuation> {
+    let dividend_per_share = financials
+        .dividend_per_share
+        .filter(|dividend| *dividend > 0.0)
+        .ok_or_else(|| Error::msg("No dividends to discount"))?;
+    let growth = financials
+        .dividend_growth_5_yr_avg
+        .ok_or_else(|| Error::msg("No dividend growth"))?
+        / 100.0;
+    if quote.c <= 0.0 {
+        return Err(Error::msg("No current price"));
+    }
+
+    let ddm_config = &analysis_config.ddm;
+    let required_return =
+        aaa_bond_yield(information, analysis_config) + ddm_config.equity_risk_premium;
+    let two_stage_value = two_stage_value(
+        dividend_per_share,
+        growth,
+        ddm_config.high_growth_years,
+        ddm_config.terminal_growth,
+        required_return,
+    )?;
+    let (gordon_value, gordon_error) =
+        match gordon_growth_value(dividend_per_share, growth, required_return) {
+            Ok(value) => (Some(value), None),
+            Err(error) => (None, Some(error.to_string())),
+        };
+    Ok(DdmValuation {
+        dividend_per_share,
+        growth,
+        required_return,
+        gordon_value,
+        gordon_error,
+        two_stage_value,
+        implied_yield: dividend_per_share * (1.0 + growth) / quote.c + growth,
+        margin_of_safety: margin_of_safety(two_stage_value, quote.c),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{analysis_config, financials, information, quote};
+
+    #[test]
+    fn test_ddm_valuation() -> Result<()> {
+        // Dividend of 1 growing 6%, required return 3.36% + 6.64% = 10%
+        let financials = financials(8.0);
+        let mut analysis_config = analysis_config();
+        analysis_config.ddm = DdmConfig {
+            equity_risk_premium: 0.0664,
+            high_growth_years: 1,
+            terminal_growth: 0.0,
+        };
+
+        let valuation = ddm_valuation(
+            &financials,
+            &information("DDM"),
+            &quote(20.0),
+            &analysis_config,
+        )?;
+        // 1.06 / 4%
+        assert!((valuation.gordon_value.unwrap() - 26.5).abs() < 1e-9);
+        assert_eq!(valuation.gordon_error, None);
+        // 1.06 / 1.1 + (1.06 / 10%) / 1.1
+        assert!((valuation.two_stage_value - 10.6).abs() < 1e-9);
+        assert!((valuation.implied_yield - 0.113).abs() < 1e-9);
+        assert!(valuation.margin_of_safety.unwrap() < 0.0);
+
+        // The Gordon model needs the growth below the required return
+        assert!(gordon_growth_value(1.0, 0.1, 0.1).is_err());
+        let mut high_growth = financials.clone();
+        high_growth.dividend_growth_5_yr_avg = Some(12.0);
+        let valuation = ddm_valuation(
+            &high_growth,
+            &information("DDM"),
+            &quote(20.0),
+            &analysis_config,
+        )?;
+        assert_eq!(valuation.gordon_value, None);
+        assert_eq!(
+            valuation.gordon_error.as_deref(),
+            Some("Dividend growth of 12.00% is not below the required return of 10.00%")
+        );
+        // The two-stage value still discounts the dividends at the terminal growth
+        assert!(valuation.two_stage_value > 0.0);
+
+        analysis_config.ddm.terminal_growth = 0.12;
+        assert!(ddm_valuation(
+            &financials,
+            &information("DDM"),
+            &quote(20.0),
+            &analysis_config
+        )
+        .is_err());
+        let mut no_dividends = financials;
+        no_dividends.dividend_per_share = None;
+        assert!(ddm_valuation(
+            &no_dividends,
+            &information("DDM"),
+            &quote(20.0),
+            &analysis_config
+        )
+        .is_err());
+        Ok(())
+    }
+}
@@ -1,15 +1,35 @@
+use crate::alerts::{AlertConfig, AlertDispatcher};
+use crate::altman::{altman_z_score, AltmanConfig, AltmanZScore, AltmanZone};
+use crate::beneish::{beneish_m_score, BeneishConfig, BeneishMScore};
+use crate::dcf::{
+    check_dcf_margin_of_safety, dcf_valuation, implied_growth, DcfConfig, DcfValuation,
+    ImpliedGrowth,
+};
+use crate::ddm::{ddm_valuation, DdmConfig, DdmValuation};
+use crate::fundamentals_history::FundamentalsHistory;
+use crate::magic_formula::MagicFormulaConfig;
+use crate::net_net::{evaluate_net_net, NetNetConfig, NetNetValuation};
+use crate::piotroski::{piotroski_f_score, PiotroskiConfig, PiotroskiFScore};
+use crate::quote_streaming::{QuoteMonitor, QuoteStream};
+use crate::rules::{deserialize_rules, evaluate_rules, Rule};
+use crate::scoring::{composite_score, RankingKey};
+use crate::settings::load_settings;
 use crate::stock_data_fetching::{
     extract_stock_list_from_exchange, CompanyFinancials, CompanyInformation, CompanyQuote,
-    DataFetchConfig, StockInfo,
+    DataFetchConfig, StockCandles, StockInfo,
 };
+use crate::storage::Storage;
+use crate::universe_filter::UniverseFilter;
 use anyhow::{Error, Result};
+use chrono::{NaiveDate, TimeZone, Utc};
 use config::Config;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use tokio::time::sleep;
 use tokio::time::Duration;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AnalysisConfig {
     pub pe_limits: [f64; 2],         // ratio
     pub pb_limits: [f64; 2],         // ratio
@@ -18,9 +38,147 @@ pub struct AnalysisConfig {
     pub dividend_growth_5y_min: f64, // In percent / 100
     pub current_ratio_min: f64,      // ratio
     pub debt_equity_max: f64,        // ratio
+    // Maximum long term debt as a multiple of net working capital
+    #[serde(default = "default_debt_working_capital_max")]
+    pub debt_working_capital_max: f64,
     pub market_cap_min: f64,         // In millions
     pub nor_aaa_10y_bond_yield: f64, // In percent / 100
     pub us_aaa_10y_bond_yield: f64,  // In percent / 100
+    // Weights of the criteria in the composite score, criteria left out weigh 1
+    #[serde(default)]
+    pub score_weights: HashMap<String, f64>,
+    #[serde(default = "default_top_n")]
+    pub top_n: usize, // Number of stocks in the ranking
+    #[serde(default)]
+    pub ranking_key: RankingKey,
+    #[serde(default)]
+    pub piotroski: PiotroskiConfig,
+    #[serde(default)]
+    pub altman: AltmanConfig,
+    #[serde(default)]
+    pub beneish: BeneishConfig,
+    #[serde(default)]
+    pub graham_number: GrahamNumberConfig,
+    #[serde(default)]
+    pub net_net: NetNetConfig,
+    #[serde(default)]
+    pub magic_formula: MagicFormulaConfig,
+    #[serde(default)]
+    pub dcf: DcfConfig,
+    #[serde(default)]
+    pub ddm: DdmConfig,
+    // Applied in order on top of the thresholds above, see ThresholdOverride
+    #[serde(default)]
+    pub overrides: Vec<ThresholdOverride>,
+    // Criteria written as expressions on the metrics, see rules
+    #[serde(default, deserialize_with = "deserialize_rules")]
+    pub rules: Vec<Rule>,
+}
+
+// The thresholds of the defensive criteria that overrides can replace
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Thresholds {
+    pub pe_limits: [f64; 2],
+    pub pb_limits: [f64; 2],
+    pub earnings_growth_5y_min: f64,
+    pub dividend_per_share_min: f64,
+    pub dividend_growth_5y_min: f64,
+    pub current_ratio_min: f64,
+    pub debt_equity_max: f64,
+    pub debt_working_capital_max: f64,
+    pub market_cap_min: f64,
+}
+
+// Replaces the thresholds it sets for the companies matching its Finnhub industry and/or country,
+// a block without either matches every company. Later matching blocks win.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ThresholdOverride {
+    pub finnhub_industry: Option<String>,
+    pub country: Option<String>,
+    pub pe_limits: Option<[f64; 2]>,
+    pub pb_limits: Option<[f64; 2]>,
+    pub earnings_growth_5y_min: Option<f64>,
+    pub dividend_per_share_min: Option<f64>,
+    pub dividend_growth_5y_min: Option<f64>,
+    pub current_ratio_min: Option<f64>,
+    pub debt_equity_max: Option<f64>,
+    pub debt_working_capital_max: Option<f64>,
+    pub market_cap_min: Option<f64>,
+}
+
+impl ThresholdOverride {
+    pub fn matches(&self, information: &CompanyInformation) -> bool {
+        self.finnhub_industry
+            .as_ref()
+            .is_none_or(|industry| *industry == information.finnhub_industry)
+            && self
+                .country
+                .as_ref()
+                .is_none_or(|country| *country == information.country)
+    }
+
+    fn apply(&self, thresholds: &mut Thresholds) {
+        let Thresholds {
+            pe_limits,
+            pb_limits,
+            earnings_growth_5y_min,
+            dividend_per_share_min,
+            dividend_growth_5y_min,
+            current_ratio_min,
+            debt_equity_max,
+            debt_working_capital_max,
+            market_cap_min,
+        } = thresholds;
+        *pe_limits = self.pe_limits.unwrap_or(*pe_limits);
+        *pb_limits = self.pb_limits.unwrap_or(*pb_limits);
+        *earnings_growth_5y_min = self
+            .earnings_growth_5y_min
+            .unwrap_or(*earnings_growth_5y_min);
+        *dividend_per_share_min = self
+            .dividend_per_share_min
+            .unwrap_or(*dividend_per_share_min);
+        *dividend_growth_5y_min = self
+            .dividend_growth_5y_min
+            .unwrap_or(*dividend_growth_5y_min);
+        *current_ratio_min = self.current_ratio_min.unwrap_or(*current_ratio_min);
+        *debt_equity_max = self.debt_equity_max.unwrap_or(*debt_equity_max);
+        *debt_working_capital_max = self
+            .debt_working_capital_max
+            .unwrap_or(*debt_working_capital_max);
+        *market_cap_min = self.market_cap_min.unwrap_or(*market_cap_min);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct GrahamNumberConfig {
+    #[serde(default = "default_pe_times_pb_max")]
+    pub pe_times_pb_max: f64,
+    // Whether the combined test replaces the P/B limits in the verdict, so that a moderately high
+    // P/B can be offset by a low P/E
+    #[serde(default)]
+    pub replaces_pb: bool,
+}
+
+fn default_pe_times_pb_max() -> f64 {
+    22.5
+}
+
+impl Default for GrahamNumberConfig {
+    fn default() -> Self {
+        Self {
+            pe_times_pb_max: default_pe_times_pb_max(),
+            replaces_pb: false,
+        }
+    }
+}
+
+fn default_debt_working_capital_max() -> f64 {
+    1.1
+}
+
+fn default_top_n() -> usize {
+    20
 }
 
 impl AnalysisConfig {
@@ -39,6 +197,47 @@ impl AnalysisConfig {
             .unwrap()
     }
 
+    pub fn thresholds(&self) -> Thresholds {
+        Thresholds {
+            pe_limits: self.pe_limits,
+            pb_limits: self.pb_limits,
+            earnings_growth_5y_min: self.earnings_growth_5y_min,
+            dividend_per_share_min: self.dividend_per_share_min,
+            dividend_growth_5y_min: self.dividend_growth_5y_min,
+            current_ratio_min: self.current_ratio_min,
+            debt_equity_max: self.debt_equity_max,
+            debt_working_capital_max: self.debt_working_capital_max,
+            market_cap_min: self.market_cap_min,
+        }
+    }
+
+    pub fn effective_thresholds(&self, information: &CompanyInformation) -> Thresholds {
+        let mut thresholds = self.thresholds();
+        for threshold_override in &self.overrides {
+            if threshold_override.matches(information) {
+                threshold_override.apply(&mut thresholds);
+            }
+        }
+        thresholds
+    }
+
+    // The config with the thresholds of the overrides matching the company
+    pub fn for_company(&self, information: &CompanyInformation) -> AnalysisConfig {
+        let thresholds = self.effective_thresholds(information);
+        AnalysisConfig {
+            pe_limits: thresholds.pe_limits,
+            pb_limits: thresholds.pb_limits,
+            earnings_growth_5y_min: thresholds.earnings_growth_5y_min,
+            dividend_per_share_min: thresholds.dividend_per_share_min,
+            dividend_growth_5y_min: thresholds.dividend_growth_5y_min,
+            current_ratio_min: thresholds.current_ratio_min,
+            debt_equity_max: thresholds.debt_equity_max,
+            debt_working_capital_max: thresholds.debt_working_capital_max,
+            market_cap_min: thresholds.market_cap_min,
+            ..self.clone()
+        }
+    }
+
     pub fn to_file(&self, filename: &str) {
         let serialized_cfg = serde_json::to_string(&self).unwrap();
         println!("{serialized_cfg}");
@@ -101,20 +300,11 @@ pub fn check_earnings_growth(
     )
 }
 
-pub fn check_pb(
-    financials: &CompanyFinancials,
-    industry: &String,
-    analysis_config: &AnalysisConfig,
-) -> Result<bool> {
+pub fn check_pb(financials: &CompanyFinancials, analysis_config: &AnalysisConfig) -> Result<bool> {
     let pb = financials.pb_ratio.unwrap_or(-1e12);
     if pb < -1e11 {
         return Err(Error::msg("No price/book ratio"));
     }
-    let mut limits = analysis_config.pb_limits;
-    if industry == "Technology" {
-        limits[1] *= 5.0;
-    }
-
     println!(
         "Price/book: {:.2} | Limits: {:?}",
         pb, analysis_config.pb_limits
@@ -165,7 +355,37 @@ pub fn check_working_capital(
     Ok(0.0 <= working_capital_per_share && working_capital_per_share >= 2.0 * quote.c / 3.0)
 }
 
-// total debt <= 1.1 * working capital
+// Graham: total long term debt <= 1.1 * net working capital
+pub fn check_debt_working_capital(
+    financials: &CompanyFinancials,
+    analysis_config: &AnalysisConfig,
+) -> Result<bool> {
+    let (total_long_term_debt, working_capital) = match (
+        financials.total_long_term_debt,
+        financials.total_current_assets,
+        financials.total_current_liabilities,
+    ) {
+        (Some(debt), Some(assets), Some(liabilities)) => (debt, assets - liabilities),
+        _ => {
+            return Err(Error::msg(
+                "No long term debt, current assets or current liabilities",
+            ))
+        }
+    };
+    println!(
+        "Long term debt: {:.2} | Working capital: {:.2} | Maximum multiple: {:.2}",
+        total_long_term_debt, working_capital, analysis_config.debt_working_capital_max
+    );
+    Ok(total_long_term_debt <= analysis_config.debt_working_capital_max * working_capital.max(0.0))
+}
+
+// Long term debt as a multiple of net working capital, None without positive working capital
+pub fn debt_working_capital(financials: &CompanyFinancials) -> Option<f64> {
+    let total_long_term_debt = financials.total_long_term_debt?;
+    let working_capital = financials.total_current_assets? - financials.total_current_liabilities?;
+    (working_capital > 0.0).then(|| total_long_term_debt / working_capital)
+}
+
 // price < 1.2 * net_tangible_assets_per_share
 // working capital per share > price per share => bra
 // asset values per share >= 2/3 * price per share => bra
@@ -197,6 +417,480 @@ pub fn check_current_ratio(
     Ok(current_ratio >= analysis_config.current_ratio_min)
 }
 
+// Graham Number: sqrt(22.5 * EPS * BVPS), the highest price a defensive investor should pay
+pub fn graham_number(financials: &CompanyFinancials) -> Result<f64> {
+    let earnings_per_share = financials.earnings_per_share.unwrap_or(-1e12);
+    let book_value_per_share = financials.book_value_per_share.unwrap_or(-1e12);
+    if earnings_per_share <= 0.0 || book_value_per_share <= 0.0 {
+        return Err(Error::msg(
+            "No positive earnings or book value per share for the Graham Number",
+        ));
+    }
+    Ok((22.5 * earnings_per_share * book_value_per_share).sqrt())
+}
+
+// P/E * P/B at the current price, equal to 22.5 * (price / Graham Number)^2
+pub fn pe_times_pb(financials: &CompanyFinancials, quote: &CompanyQuote) -> Result<f64> {
+    let number = graham_number(financials)?;
+    if quote.c <= 0.0 {
+        return Err(Error::msg("No price"));
+    }
+    Ok(22.5 * (quote.c / number).powi(2))
+}
+
+pub fn check_pe_times_pb(
+    financials: &CompanyFinancials,
+    quote: &CompanyQuote,
+    analysis_config: &AnalysisConfig,
+) -> Result<bool> {
+    let product = pe_times_pb(financials, quote)?;
+    let number = graham_number(financials)?;
+    println!(
+        "Graham Number: {:.2} | Price/Graham Number: {:.2} | P/E x P/B: {:.2} | Maximum: {:.2}",
+        number,
+        quote.c / number,
+        product,
+        analysis_config.graham_number.pe_times_pb_max
+    );
+    Ok(product <= analysis_config.graham_number.pe_times_pb_max)
+}
+
+pub fn check_piotroski_f_score(
+    f_score: &Result<PiotroskiFScore>,
+    analysis_config: &AnalysisConfig,
+) -> Result<bool> {
+    let f_score = match f_score {
+        Ok(f_score) => f_score,
+        Err(error) => return Err(Error::msg(error.to_string())),
+    };
+    println!(
+        "Piotroski F-score {}: {} | Minimum: {} | Failed signals: {:?}",
+        f_score.year,
+        f_score.score,
+        analysis_config.piotroski.min_score,
+        f_score.failed_signals()
+    );
+    Ok(f_score.score >= analysis_config.piotroski.min_score)
+}
+
+pub fn check_altman_z_score(z_score: &Result<AltmanZScore>) -> Result<bool> {
+    let z_score = match z_score {
+        Ok(z_score) => z_score,
+        Err(error) => return Err(Error::msg(error.to_string())),
+    };
+    println!(
+        "Altman Z-score {} ({:?}): {:.2} | Zone: {:?}",
+        z_score.year, z_score.model, z_score.z_score, z_score.zone
+    );
+    Ok(z_score.zone != AltmanZone::Distress)
+}
+
+pub fn check_beneish_m_score(m_score: &Result<BeneishMScore>) -> Result<bool> {
+    let m_score = match m_score {
+        Ok(m_score) => m_score,
+        Err(error) => return Err(Error::msg(error.to_string())),
+    };
+    println!(
+        "Beneish M-score {}: {:.2} | Manipulation risk: {} | Neutral indices: {:?}",
+        m_score.year, m_score.m_score, m_score.flagged, m_score.neutral_indices
+    );
+    Ok(!m_score.flagged)
+}
+
+pub fn aaa_bond_yield(information: &CompanyInformation, analysis_config: &AnalysisConfig) -> f64 {
+    if information.country == "NO" {
+        analysis_config.nor_aaa_10y_bond_yield
+    } else {
+        analysis_config.us_aaa_10y_bond_yield
+    }
+}
+
+// Graham's revised formula: V = EPS * (8.5 + 2g) * 4.4 / Y, with g the 5y earnings growth in
+// percent and Y the current AAA bond yield in percent
+pub fn graham_intrinsic_value(
+    financials: &CompanyFinancials,
+    information: &CompanyInformation,
+    analysis_config: &AnalysisConfig,
+) -> Result<f64> {
+    let earnings_per_share = financials.earnings_per_share.unwrap_or(-1e12);
+    let earnings_growth_5_yr_avg = financials.earnings_growth_5_yr_avg.unwrap_or(-1e12);
+    if earnings_per_share < -1e11 || earnings_growth_5_yr_avg < -1e11 {
+        return Err(Error::msg("No earnings per share or earnings growth"));
+    }
+    let bond_yield = 100.0 * aaa_bond_yield(information, analysis_config);
+    Ok(earnings_per_share * (8.5 + 2.0 * earnings_growth_5_yr_avg) * 4.4 / bond_yield)
+}
+
+// Fraction of the intrinsic value that the current price is below it, negative when overpriced.
+// None when the intrinsic value is not positive, e.g. from negative earnings.
+pub fn margin_of_safety(intrinsic_value: f64, price: f64) -> Option<f64> {
+    (intrinsic_value > 0.0).then(|| (intrinsic_value - price) / intrinsic_value)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CriterionResult {
+    pub name: String,
+    pub passed: bool,
+    pub required: bool, // Whether failing the criterion fails the stock
+    pub value: Option<f64>, // The metric behind the verdict
+    pub lower_limit: Option<f64>,
+    pub upper_limit: Option<f64>,
+    pub error: Option<String>, // Set when the metric is missing
+}
+
+impl CriterionResult {
+    pub fn new(name: &str, check: Result<bool>, required: bool, value: Option<f64>) -> Self {
+        let (passed, error) = match check {
+            Ok(passed) => (passed, None),
+            Err(error) => (false, Some(error.to_string())),
+        };
+        Self {
+            name: name.to_string(),
+            passed,
+            required,
+            value,
+            lower_limit: None,
+            upper_limit: None,
+            error,
+        }
+    }
+
+    pub fn with_limits(mut self, lower_limit: Option<f64>, upper_limit: Option<f64>) -> Self {
+        self.lower_limit = lower_limit;
+        self.upper_limit = upper_limit;
+        self
+    }
+
+    // E.g. "pe 25.10 above 22.50"
+    pub fn describe(&self) -> String {
+        if let Some(error) = &self.error {
+            return format!("{}: {}", self.name, error);
+        }
+        let value = match self.value {
+            Some(value) => value,
+            None => {
+                return format!(
+                    "{} {}",
+                    self.name,
+                    if self.passed { "passed" } else { "failed" }
+                )
+            }
+        };
+        match (self.lower_limit, self.upper_limit) {
+            (_, Some(upper_limit)) if value > upper_limit => {
+                format!("{} {:.2} above {:.2}", self.name, value, upper_limit)
+            }
+            (Some(lower_limit), _) if value < lower_limit => {
+                format!("{} {:.2} below {:.2}", self.name, value, lower_limit)
+            }
+            _ => format!("{} {:.2}", self.name, value),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StockReport {
+    pub stock_info: StockInfo,
+    pub financials: CompanyFinancials,
+    pub information: CompanyInformation,
+    pub quote: CompanyQuote,
+    pub criteria: Vec<CriterionResult>,
+    pub intrinsic_value: Option<f64>,
+    pub margin_of_safety: Option<f64>,
+    #[serde(default)]
+    pub graham_number: Option<f64>,
+    #[serde(default)]
+    pub price_to_graham_number: Option<f64>,
+    pub passed: bool, // All required criteria passed
+    #[serde(default)]
+    pub score: Option<f64>, // Composite score in [-1, 1], see scoring
+    #[serde(default)]
+    pub piotroski_f_score: Option<PiotroskiFScore>, // With the breakdown per signal
+    #[serde(default)]
+    pub altman_z_score: Option<AltmanZScore>,
+    #[serde(default)]
+    pub beneish_m_score: Option<BeneishMScore>, // With the manipulation risk flag
+    #[serde(default)]
+    pub net_net: Option<NetNetValuation>, // Set by the net-net screen
+    #[serde(default)]
+    pub dcf: Option<DcfValuation>, // With the value per share of every scenario
+    #[serde(default)]
+    pub implied_growth: Option<ImpliedGrowth>, // Of the reverse DCF
+    #[serde(default)]
+    pub ddm: Option<DdmValuation>, // Dividend payers only
+    #[serde(default)]
+    pub thresholds: Option<Thresholds>, // After the overrides matching the company
+}
+
+impl StockReport {
+    // A report without criteria, it passes until a required criterion fails
+    pub fn new(
+        stock_info: &StockInfo,
+        financials: CompanyFinancials,
+        information: CompanyInformation,
+        quote: CompanyQuote,
+    ) -> Self {
+        Self {
+            stock_info: stock_info.clone(),
+            financials,
+            information,
+            quote,
+            criteria: Vec::new(),
+            intrinsic_value: None,
+            margin_of_safety: None,
+            graham_number: None,
+            price_to_graham_number: None,
+            passed: true,
+            score: None,
+            piotroski_f_score: None,
+            altman_z_score: None,
+            beneish_m_score: None,
+            net_net: None,
+            dcf: None,
+            implied_growth: None,
+            ddm: None,
+            thresholds: None,
+        }
+    }
+
+    pub fn criterion(&self, name: &str) -> Option<&CriterionResult> {
+        self.criteria.iter().find(|criterion| criterion.name == name)
+    }
+
+    pub fn add_criterion(&mut self, criterion: CriterionResult) {
+        if criterion.required && !criterion.passed {
+            self.passed = false;
+        }
+        self.criteria.push(criterion);
+    }
+
+    pub fn failed_criteria(&self) -> Vec<&CriterionResult> {
+        self.criteria
+            .iter()
+            .filter(|criterion| criterion.required && !criterion.passed)
+            .collect()
+    }
+}
+
+pub fn working_capital_per_share(
+    financials: &CompanyFinancials,
+    information: &CompanyInformation,
+) -> Option<f64> {
+    let total_current_assets = financials.total_current_assets?;
+    let total_current_liabilities = financials.total_current_liabilities?;
+    Some((total_current_assets - total_current_liabilities) / information.shares_outstanding)
+}
+
+pub fn criterion_label(name: &str) -> String {
+    match name {
+        "pe" => "P/E".to_string(),
+        "pb" => "P/B".to_string(),
+        "debt_equity" => "DEBT/EQUITY".to_string(),
+        _ => name.replace('_', " ").to_uppercase(),
+    }
+}
+
+// Runs every criterion on already fetched data, unlike check_stock it does not stop at the first
+// failing criterion
+pub fn evaluate_stock(
+    stock_info: &StockInfo,
+    financials: CompanyFinancials,
+    information: CompanyInformation,
+    quote: CompanyQuote,
+    analysis_config: &AnalysisConfig,
+) -> StockReport {
+    println!("{}: Started check...", stock_info.symbol);
+
+    let company_config = analysis_config.for_company(&information);
+    let analysis_config = &company_config;
+    let intrinsic_value = graham_intrinsic_value(&financials, &information, analysis_config).ok();
+    let number = graham_number(&financials).ok();
+    let mut report = StockReport::new(stock_info, financials, information, quote);
+    report.intrinsic_value = intrinsic_value;
+    report.margin_of_safety = intrinsic_value.and_then(|value| margin_of_safety(value, quote.c));
+    report.graham_number = number;
+    report.price_to_graham_number = number.map(|number| quote.c / number);
+    report.thresholds = Some(analysis_config.thresholds());
+    let financials = &report.financials;
+    let information = &report.information;
+    let f_score = piotroski_f_score(&financials.reported_statements);
+    let z_score = altman_z_score(
+        &financials.reported_statements,
+        information,
+        &analysis_config.altman,
+    );
+    let m_score = beneish_m_score(&financials.reported_statements, &analysis_config.beneish);
+    let dcf = dcf_valuation(financials, information, &quote, analysis_config);
+
+    let mut criteria = vec![
+        CriterionResult::new(
+            "market_cap",
+            Ok(check_market_cap(information, analysis_config)),
+            true,
+            Some(information.market_cap),
+        )
+        .with_limits(Some(analysis_config.market_cap_min), None),
+        CriterionResult::new(
+            "pe",
+            check_pe(financials, analysis_config),
+            true,
+            financials.pe_ratio,
+        )
+        .with_limits(
+            Some(analysis_config.pe_limits[0]),
+            Some(analysis_config.pe_limits[1]),
+        ),
+        CriterionResult::new(
+            "dividends",
+            check_dividends(financials, analysis_config),
+            true,
+            financials.dividend_growth_5_yr_avg,
+        )
+        .with_limits(Some(analysis_config.dividend_growth_5y_min), None),
+        CriterionResult::new(
+            "earnings_growth",
+            check_earnings_growth(financials, analysis_config),
+            false,
+            financials.earnings_growth_5_yr_avg,
+        )
+        .with_limits(Some(analysis_config.earnings_growth_5y_min), None),
+        CriterionResult::new(
+            "pb",
+            check_pb(financials, analysis_config),
+            !analysis_config.graham_number.replaces_pb,
+            financials.pb_ratio,
+        )
+        .with_limits(
+            Some(analysis_config.pb_limits[0]),
+            Some(analysis_config.pb_limits[1]),
+        ),
+        CriterionResult::new(
+            "pe_times_pb",
+            check_pe_times_pb(financials, &quote, analysis_config),
+            analysis_config.graham_number.replaces_pb,
+            pe_times_pb(financials, &quote).ok(),
+        )
+        .with_limits(None, Some(analysis_config.graham_number.pe_times_pb_max)),
+        CriterionResult::new(
+            "debt_equity",
+            check_debt_equity(financials, analysis_config),
+            true,
+            financials.total_debt_to_total_equity,
+        )
+        .with_limits(None, Some(analysis_config.debt_equity_max)),
+        CriterionResult::new(
+            "working_capital",
+            check_working_capital(financials, information, &quote),
+            true,
+            working_capital_per_share(financials, information),
+        )
+        .with_limits(Some(2.0 * quote.c / 3.0), None),
+        CriterionResult::new(
+            "debt_working_capital",
+            check_debt_working_capital(financials, analysis_config),
+            true,
+            debt_working_capital(financials),
+        )
+        .with_limits(None, Some(analysis_config.debt_working_capital_max)),
+        CriterionResult::new(
+            "piotroski_f_score",
+            check_piotroski_f_score(&f_score, analysis_config),
+            analysis_config.piotroski.required,
+            f_score.as_ref().ok().map(|f_score| f_score.score as f64),
+        )
+        .with_limits(Some(analysis_config.piotroski.min_score as f64), None),
+        CriterionResult::new(
+            "altman_z_score",
+            check_altman_z_score(&z_score),
+            // Missing data is only informational, the exclusion is for the distress zone
+            analysis_config.altman.exclude_distress && z_score.is_ok(),
+            z_score.as_ref().ok().map(|z_score| z_score.z_score),
+        )
+        .with_limits(
+            z_score.as_ref().ok().map(|z_score| z_score.distress_limit),
+            None,
+        ),
+        CriterionResult::new(
+            "beneish_m_score",
+            check_beneish_m_score(&m_score),
+            // As for the Z-score, only a computed M-score can exclude the stock
+            analysis_config.beneish.exclude_flagged && m_score.is_ok(),
+            m_score.as_ref().ok().map(|m_score| m_score.m_score),
+        )
+        .with_limits(None, Some(analysis_config.beneish.threshold)),
+        CriterionResult::new(
+            "dcf_margin_of_safety",
+            check_dcf_margin_of_safety(&dcf, analysis_config),
+            analysis_config.dcf.required,
+            dcf.as_ref()
+                .ok()
+                .and_then(|dcf| dcf.scenario("base"))
+                .and_then(|base| base.margin_of_safety),
+        )
+        .with_limits(Some(analysis_config.dcf.margin_of_safety_min), None),
+    ];
+    criteria.extend(evaluate_rules(
+        &analysis_config.rules,
+        financials,
+        information,
+        &quote,
+    ));
+
+    for criterion in criteria {
+        match (&criterion.error, criterion.passed) {
+            (Some(error), _) => println!("{}: {}.", stock_info.symbol, error),
+            (None, true) => println!(
+                "{}: {} METRICS SATISFIED",
+                stock_info.symbol,
+                criterion_label(&criterion.name)
+            ),
+            (None, false) => {}
+        }
+        report.add_criterion(criterion);
+    }
+    report.piotroski_f_score = f_score.ok();
+    report.altman_z_score = z_score.ok();
+    report.beneish_m_score = m_score.ok();
+    report.dcf = dcf.ok();
+    report.implied_growth = implied_growth(
+        &report.financials,
+        &report.information,
+        &quote,
+        analysis_config,
+    )
+    .ok();
+    report.ddm = match ddm_valuation(
+        &report.financials,
+        &report.information,
+        &quote,
+        analysis_config,
+    ) {
+        Ok(ddm) => {
+            let gordon_value = match (&ddm.gordon_value, &ddm.gordon_error) {
+                (Some(value), _) => format!("{value:.2}"),
+                (None, error) => error.clone().unwrap_or_default(),
+            };
+            println!(
+                "DDM two-stage value: {:.2} | Gordon value: {} | Implied yield: {:.2}",
+                ddm.two_stage_value, gordon_value, ddm.implied_yield
+            );
+            Some(ddm)
+        }
+        Err(error) => {
+            println!("{}: {}.", stock_info.symbol, error);
+            None
+        }
+    };
+    report.score = composite_score(&report.criteria, &analysis_config.score_weights);
+    println!("Finished checking {}...", stock_info.symbol);
+
+    if report.passed {
+        println!("{}: TOTAL CHECK SATISFIED: {}", stock_info.symbol, report.passed)
+    }
+
+    report
+}
+
 pub struct StockAnalyzer {
     pub data_fetch_config: DataFetchConfig,
     pub analysis_config: AnalysisConfig,
@@ -206,17 +900,19 @@ pub struct StockAnalyzer {
 
 impl StockAnalyzer {
     pub fn new(settings_filename: &str) -> Self {
-        let config_json_value = Config::builder()
-            .add_source(config::File::with_name(settings_filename))
-            .build()
-            .unwrap()
-            .try_deserialize::<serde_json::Value>()
-            .unwrap();
+        let config_json_value = load_settings(settings_filename).unwrap();
         let data_fetch_config =
             DataFetchConfig::from_json_value(config_json_value["data_fetching"].clone());
         let analysis_config =
             AnalysisConfig::from_json_value(config_json_value["analysis"].clone());
 
+        Self::with_configs(data_fetch_config, analysis_config)
+    }
+
+    pub fn with_configs(
+        data_fetch_config: DataFetchConfig,
+        analysis_config: AnalysisConfig,
+    ) -> Self {
         let client = reqwest::Client::new();
         let num_api_calls = 0_u32;
         Self {
@@ -247,7 +943,8 @@ impl StockAnalyzer {
         .await
         .expect("No stock list");
 
-        Ok(stock_list)
+        let universe_filter = UniverseFilter::new(&self.data_fetch_config.universe_filter);
+        Ok(universe_filter.apply(stock_list))
     }
 
     pub async fn get_stock_data(
@@ -287,93 +984,342 @@ impl StockAnalyzer {
         Ok((financials, information, quote))
     }
 
-    pub async fn check_stock(&mut self, stock_info: &StockInfo) -> Result<bool> {
-        let (financials, information, quote) = self.get_stock_data(stock_info).await?;
+    pub async fn get_quote(&mut self, stock_info: &StockInfo) -> Result<CompanyQuote> {
+        self.update_api_calls().await;
+        CompanyQuote::get(
+            &self.client,
+            &stock_info.symbol,
+            &self.data_fetch_config.finnhub_api_key,
+        )
+        .await
+    }
 
-        println!("{}: Started check...", stock_info.symbol);
+    pub async fn get_fundamentals_history(&mut self, symbol: &str) -> Result<FundamentalsHistory> {
+        self.update_api_calls().await;
+        FundamentalsHistory::get(
+            &self.client,
+            &symbol.to_string(),
+            &self.data_fetch_config.finnhub_api_key,
+        )
+        .await
+    }
 
-        let industry = information.industry.clone();
-        let market_cap_good = check_market_cap(&information, &self.analysis_config);
-        if market_cap_good {
-            println!("{}: MARKET CAP METRICS SATISFIED", stock_info.symbol);
-        } else {
-            return Err(Error::msg("Not big enough company"));
+    // Daily closing prices between the unix timestamps, oldest first
+    pub async fn get_price_history(
+        &mut self,
+        symbol: &str,
+        from: i64,
+        to: i64,
+    ) -> Result<Vec<(NaiveDate, f64)>> {
+        self.update_api_calls().await;
+        let candles = StockCandles::get(
+            &self.client,
+            &symbol.to_string(),
+            from,
+            to,
+            &self.data_fetch_config.finnhub_api_key,
+        )
+        .await?;
+        let mut prices = Vec::new();
+        for (timestamp, close) in candles.t.iter().zip(&candles.c) {
+            if let Some(date_time) = Utc.timestamp_opt(*timestamp, 0).single() {
+                prices.push((date_time.date_naive(), *close));
+            }
         }
+        Ok(prices)
+    }
 
-        //println!("Num api calls: {}", self.num_api_calls);
-        let pe_good = check_pe(&financials, &self.analysis_config).unwrap_or_else(|_error| {
-            println!("{}: No price/earnings ratio.", stock_info.symbol);
-            false
-        });
-        if pe_good {
-            println!("{}: P/E METRICS SATISFIED", stock_info.symbol);
-        } else {
-            return Err(Error::msg("Not good enough P/E ratio"));
-        }
+    pub async fn analyze_stock(&mut self, stock_info: &StockInfo) -> Result<StockReport> {
+        let (financials, information, quote) = self.get_stock_data(stock_info).await?;
 
-        let dividend_good =
-            check_dividends(&financials, &self.analysis_config).unwrap_or_else(|_error| {
-                println!("{}: No dividend.", stock_info.symbol);
-                false
-            });
-        if dividend_good {
-            println!("{}: DIVIDEND METRICS SATISFIED", stock_info.symbol);
-        } else {
-            return Err(Error::msg("Not good enough dividends"));
-        }
+        Ok(evaluate_stock(
+            stock_info,
+            financials,
+            information,
+            quote,
+            &self.analysis_config,
+        ))
+    }
 
-        let earnings_growth_good = check_earnings_growth(&financials, &self.analysis_config)
-            .unwrap_or_else(|_error| {
-                println!("{}: No earnings growth.", stock_info.symbol);
-                false
-            });
-        if earnings_growth_good {
-            println!("{}: EARNINGS GROWTH METRICS SATISFIED", stock_info.symbol);
-        }
+    pub async fn analyze_net_net(&mut self, stock_info: &StockInfo) -> Result<StockReport> {
+        let (financials, information, quote) = self.get_stock_data(stock_info).await?;
 
-        let pb_good =
-            check_pb(&financials, &industry, &self.analysis_config).unwrap_or_else(|_error| {
-                println!("{}: No price/book ratio.", stock_info.symbol);
-                false
-            });
-        if pb_good {
-            println!("{}: P/B METRICS SATISFIED", stock_info.symbol);
+        Ok(evaluate_net_net(
+            stock_info,
+            financials,
+            information,
+            quote,
+            &self.analysis_config.net_net,
+        ))
+    }
+
+    pub async fn check_stock(&mut self, stock_info: &StockInfo) -> Result<bool> {
+        let report = self.analyze_stock(stock_info).await?;
+
+        let gates = [
+            ("market_cap", "Not big enough company"),
+            ("pe", "Not good enough P/E ratio"),
+            ("dividends", "Not good enough dividends"),
+        ];
+        for (name, message) in gates {
+            if report.criterion(name).is_some_and(|criterion| !criterion.passed) {
+                return Err(Error::msg(message));
+            }
+        }
+        if self.analysis_config.altman.exclude_distress
+            && report
+                .altman_z_score
+                .as_ref()
+                .is_some_and(|z_score| z_score.zone == AltmanZone::Distress)
+        {
+            return Err(Error::msg("Financially distressed"));
         }
 
-        let debt_equity_good = check_debt_equity(&financials, &self.analysis_config)
-            .unwrap_or_else(|_error| {
-                println!("{}: No debt/equity ratio.", stock_info.symbol);
-                false
-            });
-        if debt_equity_good {
-            println!("{}: DEBT/EQUITY METRICS SATISFIED", stock_info.symbol);
+        Ok(report.passed)
+    }
+
+    // Re-evaluates the price dependent checks and the price based alert rules on every trade
+    pub async fn monitor_quotes(
+        &mut self,
+        stocks: &[StockInfo],
+        alert_config: &AlertConfig,
+        storage: &Storage,
+    ) -> Result<()> {
+        let alert_dispatcher = AlertDispatcher::new(alert_config.clone());
+        let mut monitor = QuoteMonitor::new();
+        for stock_info in stocks {
+            let (financials, information, quote) = self.get_stock_data(stock_info).await?;
+            monitor.watch(&stock_info.symbol, financials, information, quote);
         }
 
-        let working_capital_good = check_working_capital(&financials, &information, &quote)
-            .unwrap_or_else(|_error| {
-                println!("{}: No working capital.", stock_info.symbol);
-                false
-            });
-        if working_capital_good {
-            println!("{}: WORKING CAPITAL METRICS SATISFIED", stock_info.symbol);
+        let mut stream = QuoteStream::connect(
+            &self.data_fetch_config.finnhub_websocket_url,
+            &self.data_fetch_config.finnhub_api_key,
+        )
+        .await?;
+        for symbol in monitor.symbols() {
+            stream.subscribe(&symbol).await?;
         }
-        println!("Finished checking {}...", stock_info.symbol);
+        println!("Streaming quotes for {} stocks...", stocks.len());
 
-        let total_check = pe_good && pb_good && debt_equity_good && working_capital_good;
-        if total_check {
-            println!(
-                "{}: TOTAL CHECK SATISFIED: {}",
-                stock_info.symbol, total_check
-            )
+        while let Some(trades) = stream.next_trades().await? {
+            for trade in trades {
+                let check = match monitor.apply_trade(&trade, &self.analysis_config) {
+                    Some(check) => check,
+                    None => continue,
+                };
+                match check.margin_of_safety {
+                    Some(margin) => println!(
+                        "{}: Price: {:.2} | Working capital good: {} | Margin of safety: {:.1}%",
+                        check.symbol,
+                        check.price,
+                        check.working_capital_good,
+                        100.0 * margin
+                    ),
+                    None => println!(
+                        "{}: Price: {:.2} | Working capital good: {}",
+                        check.symbol, check.price, check.working_capital_good
+                    ),
+                }
+                if let Some(report) = monitor.report(&check) {
+                    if let Err(error) = alert_dispatcher
+                        .process_quote_reports(storage, &[report])
+                        .await
+                    {
+                        println!("{}: Could not send alerts: {}", check.symbol, error);
+                    }
+                }
+            }
         }
+        println!("Quote stream closed");
 
-        Ok(total_check)
+        Ok(())
     }
 }
 
+#[cfg(test)]
 mod tests {
-    use crate::stock_data_fetching::StockInfo;
-    use crate::financial_analysis::StockAnalyzer;
+    use crate::financial_analysis::{
+        check_debt_working_capital, debt_working_capital, evaluate_stock, AnalysisConfig,
+        StockAnalyzer, ThresholdOverride,
+    };
+    use crate::fundamentals_history::ReportedStatements;
+    use crate::stock_data_fetching::{CompanyFinancials, StockInfo};
+    use crate::test_fixtures::{analysis_config, financials, information, quote, stock_info};
+
+    #[test]
+    fn test_debt_working_capital() {
+        // Working capital 50000 - 10000, the long term debt may reach 1.1 times it
+        let mut financials = financials(10.0);
+        let analysis_config = analysis_config();
+        let evaluate = |financials: &CompanyFinancials| {
+            evaluate_stock(
+                &stock_info("AAA"),
+                financials.clone(),
+                information("AAA"),
+                quote(30.0),
+                &analysis_config,
+            )
+        };
+        let report = evaluate(&financials);
+        let criterion = report.criterion("debt_working_capital").unwrap();
+        assert_eq!(criterion.value, Some(0.2));
+        assert!(report.passed);
+
+        financials.total_long_term_debt = Some(45000.0);
+        let report = evaluate(&financials);
+        assert!(!report.criterion("debt_working_capital").unwrap().passed);
+        assert!(!report.passed);
+
+        // Without positive working capital only a debt free company passes
+        financials.total_current_liabilities = Some(60000.0);
+        financials.total_long_term_debt = Some(0.0);
+        assert_eq!(debt_working_capital(&financials), None);
+        assert!(check_debt_working_capital(&financials, &analysis_config).unwrap());
+        financials.total_long_term_debt = None;
+        assert!(check_debt_working_capital(&financials, &analysis_config).is_err());
+    }
+
+    #[test]
+    fn test_altman_exclusion() {
+        let mut financials = financials(10.0);
+        let mut analysis_config = analysis_config();
+        analysis_config.altman.exclude_distress = true;
+        let evaluate = |financials: &CompanyFinancials| {
+            evaluate_stock(
+                &stock_info("AAA"),
+                financials.clone(),
+                information("AAA"),
+                quote(30.0),
+                &analysis_config,
+            )
+        };
+
+        // Without reported statements there is no Z-score, which does not exclude the stock
+        let report = evaluate(&financials);
+        let criterion = report.criterion("altman_z_score").unwrap();
+        assert!(criterion.error.is_some() && !criterion.required);
+        assert!(report.passed);
+
+        // Z'' = 6.56 * 0.2 - 3.26 * 0.3 - 6.72 * 0.05 + 1.05 * 0.67 = 0.698
+        financials.reported_statements = vec![ReportedStatements {
+            year: 2022,
+            total_assets: Some(1000.0),
+            total_current_assets: Some(400.0),
+            total_current_liabilities: Some(200.0),
+            total_liabilities: Some(600.0),
+            retained_earnings: Some(-300.0),
+            stockholders_equity: Some(400.0),
+            revenue: Some(1500.0),
+            operating_income: Some(-50.0),
+            ..ReportedStatements::default()
+        }];
+        let report = evaluate(&financials);
+        let criterion = report.criterion("altman_z_score").unwrap();
+        assert!(!criterion.passed && criterion.required);
+        assert!(!report.passed);
+    }
+
+    #[test]
+    fn test_beneish_exclusion() {
+        let mut analysis_config = analysis_config();
+        analysis_config.beneish.exclude_flagged = true;
+
+        // Without two reported years there is no M-score, which does not exclude the stock
+        let report = evaluate_stock(
+            &stock_info("AAA"),
+            financials(10.0),
+            information("AAA"),
+            quote(30.0),
+            &analysis_config,
+        );
+        let criterion = report.criterion("beneish_m_score").unwrap();
+        assert!(criterion.error.is_some() && !criterion.required);
+        assert!(report.passed);
+    }
+
+    #[test]
+    fn test_graham_number() {
+        // Graham Number sqrt(22.5 * 3 * 25) = 41.08
+        let mut financials = financials(10.0);
+        financials.pb_ratio = Some(6.0);
+        let mut analysis_config = analysis_config();
+        let evaluate = |price: f64, analysis_config: &AnalysisConfig| {
+            evaluate_stock(
+                &stock_info("AAA"),
+                financials.clone(),
+                information("AAA"),
+                quote(price),
+                analysis_config,
+            )
+        };
+
+        let report = evaluate(30.0, &analysis_config);
+        assert!((report.graham_number.unwrap() - 41.079).abs() < 1e-3);
+        assert!((report.price_to_graham_number.unwrap() - 0.730).abs() < 1e-3);
+        let criterion = report.criterion("pe_times_pb").unwrap();
+        assert!((criterion.value.unwrap() - 12.0).abs() < 1e-9);
+        assert!(criterion.passed && !criterion.required);
+        // The P/B above its limit fails the stock unless the combined test replaces it
+        assert!(!report.passed);
+        analysis_config.graham_number.replaces_pb = true;
+        assert!(evaluate(30.0, &analysis_config).passed);
+
+        let report = evaluate(50.0, &analysis_config);
+        assert!(!report.criterion("pe_times_pb").unwrap().passed);
+        assert!(!report.passed);
+    }
+
+    #[test]
+    fn test_threshold_overrides() {
+        let mut financials = financials(10.0);
+        financials.pb_ratio = Some(10.0);
+        let mut analysis_config = analysis_config();
+        analysis_config.overrides = serde_json::from_value(serde_json::json!([
+            { "finnhub_industry": "Technology", "pb_limits": [0.4, 25.0] },
+            { "country": "NO", "pe_limits": [2.0, 8.0] },
+            { "finnhub_industry": "Technology", "country": "NO", "pb_limits": [0.4, 12.0] }
+        ]))
+        .unwrap();
+        let evaluate = |finnhub_industry: &str, country: &str| {
+            let mut information = information("AAA");
+            information.finnhub_industry = finnhub_industry.to_string();
+            information.country = country.to_string();
+            evaluate_stock(
+                &stock_info("AAA"),
+                financials.clone(),
+                information,
+                quote(30.0),
+                &analysis_config,
+            )
+        };
+
+        let report = evaluate("Retail", "US");
+        assert_eq!(report.thresholds.as_ref().unwrap().pb_limits, [0.4, 5.0]);
+        assert!(!report.criterion("pb").unwrap().passed);
+
+        let report = evaluate("Technology", "US");
+        let thresholds = report.thresholds.as_ref().unwrap();
+        assert_eq!(thresholds.pb_limits, [0.4, 25.0]);
+        assert_eq!(thresholds.pe_limits, [2.0, 22.5]);
+        let criterion = report.criterion("pb").unwrap();
+        assert!(criterion.passed);
+        assert_eq!(criterion.upper_limit, Some(25.0));
+        assert!(report.passed);
+
+        // Every matching block applies, the later ones win
+        let report = evaluate("Technology", "NO");
+        let thresholds = report.thresholds.as_ref().unwrap();
+        assert_eq!(thresholds.pb_limits, [0.4, 12.0]);
+        assert_eq!(thresholds.pe_limits, [2.0, 8.0]);
+        assert!(!report.criterion("pe").unwrap().passed);
+
+        assert!(serde_json::from_value::<ThresholdOverride>(
+            serde_json::json!({ "pe_limit": [2.0, 8.0] })
+        )
+        .is_err());
+    }
+
     #[tokio::test]
     async fn test_stock_analysis() -> anyhow::Result<()> {
         let settings_filename = "config/settings";
@@ -383,6 +1329,10 @@ mod tests {
             symbol: "AAPL".to_string(),
             currency: "USD".to_string(),
             description: "Apple".to_string(),
+            display_symbol: "AAPL".to_string(),
+            figi: "BBG000B9XRY4".to_string(),
+            mic: "XNAS".to_string(),
+            security_type: "Common Stock".to_string(),
         };
 
         let is_good = match stock_analyzer.check_stock(&stock_info).await {
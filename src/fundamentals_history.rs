@@ -0,0 +1,515 @@
+use anyhow::{Error, Result};
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, Timelike, Weekday};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+// XBRL concepts of the long-term debt, in order of preference. Filers report either the
+// noncurrent portion next to the current one or the total.
+const LONG_TERM_DEBT_CONCEPTS: [&str; 2] = ["LongTermDebtNoncurrent", "LongTermDebt"];
+
+// In Million USD like the other fundamentals, items missing from the report are None
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct BalanceSheet {
+    pub total_current_assets: Option<f64>,
+    pub total_current_liabilities: Option<f64>,
+    pub total_current_long_term_debt: Option<f64>,
+    // Including the current portion
+    #[serde(default)]
+    pub total_long_term_debt: Option<f64>,
+}
+
+impl BalanceSheet {
+    pub fn from_report(bs: &serde_json::Value) -> Result<Self> {
+        let entries = bs
+            .as_array()
+            .ok_or_else(|| Error::msg("Null value for company balance sheet."))?;
+        let mut balance_sheet = BalanceSheet {
+            total_current_assets: None,
+            total_current_liabilities: None,
+            total_current_long_term_debt: None,
+            total_long_term_debt: None,
+        };
+        let mut long_term_debt = [None; LONG_TERM_DEBT_CONCEPTS.len()];
+        for entry in entries {
+            let label = entry["label"].as_str().unwrap_or("");
+            let concept = entry["concept"].as_str().unwrap_or("");
+            let value = match entry["value"].as_f64() {
+                Some(value) => value / 1e6_f64,
+                None => continue,
+            };
+            if label == "Total current assets" {
+                balance_sheet.total_current_assets = Some(value);
+            }
+            if label == "Total current liabilities" {
+                balance_sheet.total_current_liabilities = Some(value);
+            }
+            // The label of the current portion differs between companies, the concept does not
+            let concept = concept.split_once('_').map_or(concept, |(_, name)| name);
+            if concept == "LongTermDebtCurrent" {
+                balance_sheet.total_current_long_term_debt = Some(value);
+            }
+            if let Some(index) = LONG_TERM_DEBT_CONCEPTS
+                .iter()
+                .position(|name| *name == concept)
+            {
+                long_term_debt[index] = Some(value);
+            }
+        }
+        balance_sheet.total_long_term_debt = match long_term_debt {
+            [Some(noncurrent), _] => {
+                Some(noncurrent + balance_sheet.total_current_long_term_debt.unwrap_or(0.0))
+            }
+            [None, total] => total,
+        };
+        Ok(balance_sheet)
+    }
+}
+
+// Items of the balance sheet, income statement and cash flow statement of one reported period, in
+// million USD (shares in millions). Items missing from the report are None.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ReportedStatements {
+    pub year: i32,
+    pub end_date: Option<NaiveDate>,
+    // Balance sheet
+    pub total_assets: Option<f64>,
+    pub cash: Option<f64>, // Cash and cash equivalents
+    pub total_current_assets: Option<f64>,
+    pub total_current_liabilities: Option<f64>,
+    pub total_liabilities: Option<f64>,
+    pub long_term_debt: Option<f64>,
+    pub retained_earnings: Option<f64>,
+    pub receivables: Option<f64>,
+    pub property_plant_equipment: Option<f64>,
+    pub goodwill: Option<f64>,
+    pub intangible_assets: Option<f64>, // Excluding goodwill
+    pub stockholders_equity: Option<f64>,
+    // Income statement
+    pub revenue: Option<f64>,
+    pub cost_of_revenue: Option<f64>,
+    pub gross_profit: Option<f64>,
+    pub operating_income: Option<f64>,
+    pub net_income: Option<f64>,
+    pub sga_expense: Option<f64>,
+    pub shares_outstanding: Option<f64>, // Weighted average diluted shares
+    // Cash flow statement
+    pub depreciation: Option<f64>,
+    pub operating_cash_flow: Option<f64>,
+    pub capital_expenditure: Option<f64>, // Payments, positive
+}
+
+impl ReportedStatements {
+    pub fn from_report(year: i32, end_date: NaiveDate, report: &serde_json::Value) -> Self {
+        let mut values: Vec<(&str, f64)> = Vec::new();
+        for statement in ["bs", "ic", "cf"] {
+            for entry in report[statement].as_array().into_iter().flatten() {
+                let concept = entry["concept"].as_str().unwrap_or("");
+                // E.g. "us-gaap_Assets" or "ifrs-full_Assets"
+                let concept = concept.split_once('_').map_or(concept, |(_, name)| name);
+                if let Some(value) = entry["value"].as_f64() {
+                    values.push((concept, value / 1e6_f64));
+                }
+            }
+        }
+        // The first of the XBRL concepts, in order of preference, that was reported
+        let item = |concepts: &[&str]| {
+            concepts.iter().find_map(|concept| {
+                values
+                    .iter()
+                    .find(|(reported, _)| reported == concept)
+                    .map(|(_, value)| *value)
+            })
+        };
+
+        let mut statements = ReportedStatements {
+            year,
+            end_date: Some(end_date),
+            total_assets: item(&["Assets"]),
+            cash: item(&[
+                "CashAndCashEquivalentsAtCarryingValue",
+                "CashCashEquivalentsRestrictedCashAndRestrictedCashEquivalents",
+                "CashAndCashEquivalents",
+            ]),
+            total_current_assets: item(&["AssetsCurrent"]),
+            total_current_liabilities: item(&["LiabilitiesCurrent"]),
+            total_liabilities: item(&["Liabilities"]),
+            long_term_debt: item(&LONG_TERM_DEBT_CONCEPTS),
+            retained_earnings: item(&["RetainedEarningsAccumulatedDeficit"]),
+            receivables: item(&["AccountsReceivableNetCurrent", "ReceivablesNetCurrent"]),
+            property_plant_equipment: item(&["PropertyPlantAndEquipmentNet"]),
+            goodwill: item(&["Goodwill"]),
+            intangible_assets: item(&[
+                "IntangibleAssetsNetExcludingGoodwill",
+                "FiniteLivedIntangibleAssetsNet",
+            ]),
+            stockholders_equity: item(&[
+                "StockholdersEquity",
+                "StockholdersEquityIncludingPortionAttributableToNoncontrollingInterest",
+            ]),
+            revenue: item(&[
+                "Revenues",
+                "RevenueFromContractWithCustomerExcludingAssessedTax",
+                "SalesRevenueNet",
+            ]),
+            cost_of_revenue: item(&[
+                "CostOfRevenue",
+                "CostOfGoodsAndServicesSold",
+                "CostOfGoodsSold",
+            ]),
+            gross_profit: item(&["GrossProfit"]),
+            operating_income: item(&["OperatingIncomeLoss"]),
+            net_income: item(&["NetIncomeLoss", "ProfitLoss"]),
+            sga_expense: item(&["SellingGeneralAndAdministrativeExpense"]),
+            shares_outstanding: item(&[
+                "WeightedAverageNumberOfDilutedSharesOutstanding",
+                "WeightedAverageNumberOfSharesOutstandingBasic",
+            ]),
+            depreciation: item(&[
+                "DepreciationDepletionAndAmortization",
+                "DepreciationAndAmortization",
+                "Depreciation",
+            ]),
+            operating_cash_flow: item(&["NetCashProvidedByUsedInOperatingActivities"]),
+            capital_expenditure: item(&[
+                "PaymentsToAcquirePropertyPlantAndEquipment",
+                "PaymentsToAcquireProductiveAssets",
+            ]),
+        };
+        // Not every filer reports each of a pair of items that determine each other
+        if statements.gross_profit.is_none() {
+            statements.gross_profit = statements
+                .revenue
+                .zip(statements.cost_of_revenue)
+                .map(|(revenue, cost)| revenue - cost);
+        }
+        if statements.total_liabilities.is_none() {
+            statements.total_liabilities = statements
+                .total_assets
+                .zip(statements.stockholders_equity)
+                .map(|(assets, equity)| assets - equity);
+        }
+        statements
+    }
+
+    pub fn working_capital(&self) -> Option<f64> {
+        Some(self.total_current_assets? - self.total_current_liabilities?)
+    }
+
+    // Missing capital expenditure is taken as no capital expenditure
+    pub fn free_cash_flow(&self) -> Option<f64> {
+        Some(self.operating_cash_flow? - self.capital_expenditure.unwrap_or(0.0))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReportedFiling {
+    pub form: String, // E.g. "10-K" or "10-K/A"
+    pub year: i32,
+    pub quarter: u32,        // 0 for annual reports
+    pub end_date: NaiveDate, // End of the reported period
+    pub filed_date: NaiveDate,
+    pub accepted_at: Option<NaiveDateTime>, // Eastern time, as reported by EDGAR
+    pub balance_sheet: BalanceSheet,
+    #[serde(default)]
+    pub statements: ReportedStatements,
+}
+
+// Hour of the close of the US markets, Eastern time
+const MARKET_CLOSE_HOUR: u32 = 16;
+
+impl ReportedFiling {
+    // The first trading day on which the filing is public. A filing accepted after the market
+    // close can only be traded on from the next weekday, market holidays are not accounted for.
+    pub fn public_date(&self) -> NaiveDate {
+        let mut date = match self.accepted_at {
+            Some(accepted_at) if accepted_at.hour() >= MARKET_CLOSE_HOUR => {
+                accepted_at.date() + Days::new(1)
+            }
+            Some(accepted_at) => accepted_at.date(),
+            None => self.filed_date,
+        }
+        .max(self.filed_date);
+        while matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+            date = date + Days::new(1);
+        }
+        date
+    }
+}
+
+// Finnhub dates look like "2022-10-28 00:00:00"
+fn parse_date(value: &serde_json::Value) -> Option<NaiveDate> {
+    let value = value.as_str()?;
+    NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()
+}
+
+// Reported filings of a company ordered by filing date, so that historical screens and backtests
+// only see what was public at the time
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct FundamentalsHistory {
+    pub filings: Vec<ReportedFiling>,
+}
+
+impl FundamentalsHistory {
+    pub async fn get(
+        client: &reqwest::Client,
+        symbol: &String,
+        api_key: &String,
+    ) -> Result<Self, Error> {
+        let url = format!(
+            "https://finnhub.io/api/v1/stock/financials-reported?symbol={symbol}&token={api_key}&freq=annual"
+        );
+        let url = Url::parse(&url)?;
+        let response = client
+            .get(url)
+            .send()
+            .await?
+            .json::<serde_json::Value>()
+            .await?;
+
+        Self::from_serde_json_value(&response)
+    }
+
+    // Filings without a filing date or balance sheet are left out
+    pub fn from_serde_json_value(response: &serde_json::Value) -> Result<Self, Error> {
+        let data = response["data"]
+            .as_array()
+            .ok_or_else(|| Error::msg("No reported financials"))?;
+
+        let mut filings = Vec::new();
+        for filing in data {
+            let (end_date, filed_date) = match (
+                parse_date(&filing["endDate"]),
+                parse_date(&filing["filedDate"]),
+            ) {
+                (Some(end_date), Some(filed_date)) => (end_date, filed_date),
+                _ => continue,
+            };
+            let balance_sheet = match BalanceSheet::from_report(&filing["report"]["bs"]) {
+                Ok(balance_sheet) => balance_sheet,
+                Err(_) => continue,
+            };
+            let year = filing["year"].as_i64().unwrap_or(0) as i32;
+            filings.push(ReportedFiling {
+                form: filing["form"].as_str().unwrap_or("").to_string(),
+                year,
+                quarter: filing["quarter"].as_u64().unwrap_or(0) as u32,
+                end_date,
+                filed_date,
+                accepted_at: filing["acceptedDate"].as_str().and_then(|accepted_date| {
+                    NaiveDateTime::parse_from_str(accepted_date, "%Y-%m-%d %H:%M:%S").ok()
+                }),
+                balance_sheet,
+                statements: ReportedStatements::from_report(year, end_date, &filing["report"]),
+            });
+        }
+        filings.sort_by(|a, b| {
+            (a.filed_date, a.accepted_at, a.end_date).cmp(&(
+                b.filed_date,
+                b.accepted_at,
+                b.end_date,
+            ))
+        });
+
+        Ok(Self { filings })
+    }
+
+    // The filing covering the most recent period among those public on the date. An amendment
+    // replaces the original filing of the same period once it is public.
+    pub fn as_of(&self, date: NaiveDate) -> Option<&ReportedFiling> {
+        self.filings
+            .iter()
+            .filter(|filing| filing.public_date() <= date)
+            .max_by_key(|filing| (filing.end_date, filing.filed_date, filing.accepted_at))
+    }
+
+    pub fn latest(&self) -> Option<&ReportedFiling> {
+        self.filings
+            .iter()
+            .max_by_key(|filing| (filing.end_date, filing.filed_date, filing.accepted_at))
+    }
+
+    // The statements of the latest num_periods reported periods public on the date, newest first.
+    // Amendments replace the original filing of their period.
+    pub fn statements_as_of(&self, date: NaiveDate, num_periods: usize) -> Vec<ReportedStatements> {
+        let mut periods: Vec<&ReportedFiling> = Vec::new();
+        // Filings are ordered by filing date, so a later filing of a period replaces the earlier
+        for filing in self
+            .filings
+            .iter()
+            .filter(|filing| filing.public_date() <= date)
+        {
+            periods.retain(|period| period.end_date != filing.end_date);
+            periods.push(filing);
+        }
+        periods.sort_by_key(|filing| std::cmp::Reverse(filing.end_date));
+        periods
+            .into_iter()
+            .take(num_periods)
+            .map(|filing| filing.statements.clone())
+            .collect()
+    }
+
+    pub fn recent_statements(&self, num_periods: usize) -> Vec<ReportedStatements> {
+        self.statements_as_of(NaiveDate::MAX, num_periods)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filing(form: &str, year: i32, filed_date: &str, current_assets: f64) -> serde_json::Value {
+        json!({
+            "form": form,
+            "year": year,
+            "quarter": 0,
+            "startDate": format!("{} 00:00:00", year - 1),
+            "endDate": format!("{year}-09-30 00:00:00"),
+            "filedDate": format!("{filed_date} 00:00:00"),
+            "acceptedDate": format!("{filed_date} 16:30:00"),
+            "report": {
+                "bs": [
+                    { "label": "Total current assets", "concept": "us-gaap_AssetsCurrent", "value": current_assets },
+                    { "label": "Total current liabilities", "concept": "us-gaap_LiabilitiesCurrent", "value": 100e6 },
+                    { "label": "Term debt", "concept": "us-gaap_LongTermDebtCurrent", "value": 10e6 },
+                    { "label": "Term debt", "concept": "us-gaap_LongTermDebtNoncurrent", "value": 90e6 },
+                    { "label": "Total assets", "concept": "us-gaap_Assets", "value": 10.0 * current_assets }
+                ],
+                "ic": [
+                    { "label": "Net sales", "concept": "us-gaap_RevenueFromContractWithCustomerExcludingAssessedTax", "value": 500e6 },
+                    { "label": "Cost of sales", "concept": "us-gaap_CostOfGoodsAndServicesSold", "value": 300e6 },
+                    { "label": "Net income", "concept": "us-gaap_NetIncomeLoss", "value": 50e6 }
+                ],
+                "cf": [
+                    { "label": "Cash generated by operating activities", "concept": "us-gaap_NetCashProvidedByUsedInOperatingActivities", "value": 70e6 },
+                    { "label": "Payments for acquisition of property, plant and equipment", "concept": "us-gaap_PaymentsToAcquirePropertyPlantAndEquipment", "value": 20e6 }
+                ]
+            }
+        })
+    }
+
+    fn date(text: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(text, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_as_of() -> Result<()> {
+        // Finnhub does not guarantee the order of the filings
+        let response = json!({
+            "data": [
+                filing("10-K", 2021, "2021-10-29", 300e6),
+                filing("10-K", 2022, "2022-10-28", 400e6),
+                filing("10-K/A", 2021, "2022-02-15", 350e6),
+                { "form": "10-K", "year": 2019, "report": {} }
+            ]
+        });
+        let history = FundamentalsHistory::from_serde_json_value(&response)?;
+        assert_eq!(history.filings.len(), 3);
+        assert_eq!(history.filings[0].filed_date, date("2021-10-29"));
+
+        // Accepted after the close on Friday, so public from Monday
+        assert_eq!(history.filings[0].public_date(), date("2021-11-01"));
+        assert!(history.as_of(date("2021-10-29")).is_none());
+        assert!(history.as_of(date("2021-10-31")).is_none());
+        let filing = history.as_of(date("2021-12-31")).unwrap();
+        assert_eq!(filing.balance_sheet.total_current_assets, Some(300.0));
+        let original = history.as_of(date("2022-02-15")).unwrap();
+        assert_eq!(original.form, "10-K");
+        let amended = history.as_of(date("2022-02-16")).unwrap();
+        assert_eq!(amended.form, "10-K/A");
+        assert_eq!(amended.balance_sheet.total_current_assets, Some(350.0));
+        // The 2022 report is not public until it is filed
+        assert_eq!(history.as_of(date("2022-10-28")).unwrap().year, 2021);
+        assert_eq!(history.as_of(date("2023-01-01")).unwrap().year, 2022);
+        assert_eq!(
+            history
+                .latest()
+                .unwrap()
+                .balance_sheet
+                .total_current_liabilities,
+            Some(100.0)
+        );
+        assert_eq!(
+            history
+                .latest()
+                .unwrap()
+                .balance_sheet
+                .total_current_long_term_debt,
+            Some(10.0)
+        );
+        assert_eq!(
+            history.latest().unwrap().balance_sheet.total_long_term_debt,
+            Some(100.0)
+        );
+
+        let statements = history.statements_as_of(date("2022-06-30"), 3);
+        assert_eq!(statements.len(), 1);
+        assert_eq!(statements[0].total_assets, Some(3500.0));
+        assert_eq!(history.statements_as_of(date("2022-10-30"), 3).len(), 1);
+        assert_eq!(history.statements_as_of(date("2022-10-31"), 3).len(), 2);
+
+        // Accepted before the close, public on the day it is filed
+        let mut filing = history.filings[0].clone();
+        filing.accepted_at = date("2021-10-29").and_hms_opt(9, 0, 0);
+        assert_eq!(filing.public_date(), date("2021-10-29"));
+        filing.accepted_at = None;
+        assert_eq!(filing.public_date(), date("2021-10-29"));
+        let statements = history.recent_statements(3);
+        assert_eq!(
+            statements
+                .iter()
+                .map(|statements| statements.year)
+                .collect::<Vec<i32>>(),
+            vec![2022, 2021]
+        );
+        assert_eq!(statements[0].revenue, Some(500.0));
+        assert_eq!(statements[0].gross_profit, Some(200.0));
+        assert_eq!(statements[0].net_income, Some(50.0));
+        assert_eq!(statements[0].operating_cash_flow, Some(70.0));
+        assert_eq!(statements[0].free_cash_flow(), Some(50.0));
+        assert_eq!(statements[0].working_capital(), Some(300.0));
+        assert_eq!(statements[0].long_term_debt, Some(90.0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_term_debt() -> Result<()> {
+        let balance_sheet = |entries: serde_json::Value| BalanceSheet::from_report(&entries);
+
+        // The noncurrent portion is preferred, as in the reported statements
+        let total = balance_sheet(json!([
+            { "label": "Term debt", "concept": "us-gaap_LongTermDebtCurrent", "value": 10e6 },
+            { "label": "Term debt", "concept": "us-gaap_LongTermDebtNoncurrent", "value": 90e6 },
+            { "label": "Long-term debt", "concept": "us-gaap_LongTermDebt", "value": 120e6 }
+        ]))?;
+        assert_eq!(total.total_long_term_debt, Some(100.0));
+        let total = balance_sheet(json!([
+            { "label": "Long-term debt", "concept": "us-gaap_LongTermDebt", "value": 120e6 }
+        ]))?;
+        assert_eq!(total.total_long_term_debt, Some(120.0));
+
+        // The current portion is found by its concept whatever its label
+        let labelled = balance_sheet(json!([
+            {
+                "label": "Current portion of long-term debt",
+                "concept": "us-gaap_LongTermDebtCurrent",
+                "value": 15e6
+            },
+            { "label": "Long-term debt", "concept": "ifrs-full_LongTermDebtNoncurrent", "value": 85e6 }
+        ]))?;
+        assert_eq!(labelled.total_current_long_term_debt, Some(15.0));
+        assert_eq!(labelled.total_long_term_debt, Some(100.0));
+
+        // Other concepts are not taken for the long-term debt, which is then unknown
+        let other = balance_sheet(json!([
+            {
+                "label": "Long-term debt and finance leases",
+                "concept": "us-gaap_LongTermDebtAndCapitalLeaseObligations",
+                "value": 80e6
+            }
+        ]))?;
+        assert_eq!(other.total_long_term_debt, None);
+        Ok(())
+    }
+}
@@ -1,2 +1,31 @@
+pub mod alerts;
+pub mod altman;
+pub mod backtest;
+pub mod beneish;
+pub mod daemon;
+pub mod dcf;
+pub mod ddm;
 pub mod financial_analysis;
+pub mod fundamentals_history;
+pub mod magic_formula;
+pub mod net_net;
+pub mod piotroski;
+pub mod portfolio;
+pub mod quote_streaming;
+pub mod result_browser;
+pub mod rules;
+pub mod run_diff;
+pub mod scan;
+pub mod scoring;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod settings;
 pub mod stock_data_fetching;
+pub mod storage;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod universe_filter;
+pub mod watchlist;
+
+#[cfg(test)]
+mod test_fixtures;
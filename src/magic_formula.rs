@@ -0,0 +1,241 @@
+use crate::financial_analysis::StockReport;
+use crate::stock_data_fetching::{CompanyFinancials, CompanyInformation};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MagicFormulaConfig {
+    #[serde(default = "default_top_n")]
+    pub top_n: usize,
+    // Finnhub industries left out of the ranking, their balance sheets make EBIT/EV meaningless
+    #[serde(default = "default_excluded_industries")]
+    pub excluded_industries: Vec<String>,
+}
+
+fn default_top_n() -> usize {
+    30
+}
+
+fn default_excluded_industries() -> Vec<String> {
+    ["Banking", "Financial Services", "Insurance", "Utilities"]
+        .iter()
+        .map(|industry| industry.to_string())
+        .collect()
+}
+
+impl Default for MagicFormulaConfig {
+    fn default() -> Self {
+        Self {
+            top_n: default_top_n(),
+            excluded_industries: default_excluded_industries(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MagicFormulaMetrics {
+    pub ebit: f64,
+    pub enterprise_value: f64,  // Market cap + long term debt - cash
+    pub earnings_yield: f64,    // EBIT / enterprise value
+    pub return_on_capital: f64, // EBIT / (net working capital + net fixed assets)
+}
+
+// From the latest reported statements and the current market cap, in million USD
+pub fn magic_formula_metrics(
+    financials: &CompanyFinancials,
+    information: &CompanyInformation,
+) -> Result<MagicFormulaMetrics> {
+    let latest = financials
+        .reported_statements
+        .first()
+        .ok_or_else(|| Error::msg("No reported statements for the Magic Formula"))?;
+    let ebit = latest
+        .operating_income
+        .ok_or_else(|| Error::msg("No operating income"))?;
+    let debt = financials
+        .total_long_term_debt
+        .or(latest.long_term_debt)
+        .unwrap_or(0.0);
+    let enterprise_value = information.market_cap + debt - latest.cash.unwrap_or(0.0);
+    if enterprise_value <= 0.0 {
+        return Err(Error::msg("No positive enterprise value"));
+    }
+    let capital = latest
+        .working_capital()
+        .zip(latest.property_plant_equipment)
+        .map(|(working_capital, fixed_assets)| working_capital.max(0.0) + fixed_assets)
+        .ok_or_else(|| Error::msg("No working capital or net fixed assets"))?;
+    if capital <= 0.0 {
+        return Err(Error::msg(
+            "No positive working capital and net fixed assets",
+        ));
+    }
+    Ok(MagicFormulaMetrics {
+        ebit,
+        enterprise_value,
+        earnings_yield: ebit / enterprise_value,
+        return_on_capital: ebit / capital,
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MagicFormulaRank {
+    pub rank: usize, // Starting at 1
+    pub symbol: String,
+    pub metrics: MagicFormulaMetrics,
+    pub earnings_yield_rank: usize,
+    pub return_on_capital_rank: usize,
+}
+
+// Position of every stock when sorted by the metric, highest first
+fn ranks_by(
+    metrics: &[(&StockReport, MagicFormulaMetrics)],
+    value: fn(&MagicFormulaMetrics) -> f64,
+) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..metrics.len()).collect();
+    order.sort_by(|&a, &b| {
+        value(&metrics[b].1)
+            .partial_cmp(&value(&metrics[a].1))
+            .unwrap_or(Ordering::Equal)
+    });
+    let mut ranks = vec![0; metrics.len()];
+    for (position, index) in order.into_iter().enumerate() {
+        ranks[index] = position + 1;
+    }
+    ranks
+}
+
+// Greenblatt's ranking: the stocks are ranked on earnings yield and on return on capital, and the
+// top_n with the lowest sum of both ranks are returned. Stocks in the excluded industries or
+// without the metrics are left out.
+pub fn rank_magic_formula(
+    reports: &[StockReport],
+    magic_formula_config: &MagicFormulaConfig,
+) -> Vec<MagicFormulaRank> {
+    let metrics: Vec<(&StockReport, MagicFormulaMetrics)> = reports
+        .iter()
+        .filter(|report| {
+            !magic_formula_config
+                .excluded_industries
+                .contains(&report.information.finnhub_industry)
+        })
+        .filter_map(|report| {
+            magic_formula_metrics(&report.financials, &report.information)
+                .ok()
+                .map(|metrics| (report, metrics))
+        })
+        .collect();
+    let earnings_yield_ranks = ranks_by(&metrics, |metrics| metrics.earnings_yield);
+    let return_on_capital_ranks = ranks_by(&metrics, |metrics| metrics.return_on_capital);
+
+    let mut ranked: Vec<MagicFormulaRank> = metrics
+        .into_iter()
+        .enumerate()
+        .map(|(index, (report, metrics))| MagicFormulaRank {
+            rank: 0,
+            symbol: report.stock_info.symbol.clone(),
+            metrics,
+            earnings_yield_rank: earnings_yield_ranks[index],
+            return_on_capital_rank: return_on_capital_ranks[index],
+        })
+        .collect();
+    // Ties go to the higher earnings yield
+    ranked.sort_by_key(|rank| {
+        (
+            rank.earnings_yield_rank + rank.return_on_capital_rank,
+            rank.earnings_yield_rank,
+        )
+    });
+    ranked.truncate(magic_formula_config.top_n);
+    for (index, rank) in ranked.iter_mut().enumerate() {
+        rank.rank = index + 1;
+    }
+    ranked
+}
+
+pub fn magic_formula_table(ranked: &[MagicFormulaRank]) -> String {
+    let mut table = format!(
+        "{:<6}{:<10}{:>16}{:>20}{:>10}{:>10}\n",
+        "RANK", "SYMBOL", "EARNINGS YIELD", "RETURN ON CAPITAL", "EY RANK", "ROC RANK"
+    );
+    for rank in ranked {
+        table += &format!(
+            "{:<6}{:<10}{:>15.1}%{:>19.1}%{:>10}{:>10}\n",
+            rank.rank,
+            rank.symbol,
+            100.0 * rank.metrics.earnings_yield,
+            100.0 * rank.metrics.return_on_capital,
+            rank.earnings_yield_rank,
+            rank.return_on_capital_rank
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamentals_history::ReportedStatements;
+    use crate::test_fixtures::report;
+
+    // Market cap 30000 and long term debt 8000 from the fixtures
+    fn company(symbol: &str, ebit: f64, cash: f64, fixed_assets: f64) -> StockReport {
+        let mut report = report(symbol, 10.0, 30.0);
+        report.financials.reported_statements = vec![ReportedStatements {
+            year: 2022,
+            cash: Some(cash),
+            total_current_assets: Some(3000.0),
+            total_current_liabilities: Some(1000.0),
+            property_plant_equipment: Some(fixed_assets),
+            operating_income: Some(ebit),
+            ..ReportedStatements::default()
+        }];
+        report
+    }
+
+    #[test]
+    fn test_magic_formula() {
+        let cheap = company("CHEAP", 3800.0, 0.0, 36000.0);
+        let quality = company("QUALITY", 1900.0, 0.0, 0.0);
+        let poor = company("POOR", 950.0, 0.0, 8000.0);
+        let mut bank = company("BANK", 10000.0, 0.0, 0.0);
+        bank.information.finnhub_industry = "Banking".to_string();
+        let mut unprofitable = company("NONE", 0.0, 0.0, 0.0);
+        unprofitable.financials.reported_statements.clear();
+
+        let metrics = magic_formula_metrics(&cheap.financials, &cheap.information).unwrap();
+        assert_eq!(metrics.enterprise_value, 38000.0);
+        assert_eq!(metrics.earnings_yield, 0.1);
+        assert_eq!(metrics.return_on_capital, 0.1);
+
+        let ranked = rank_magic_formula(
+            &[poor, quality, bank, unprofitable, cheap],
+            &MagicFormulaConfig::default(),
+        );
+        let symbols: Vec<&str> = ranked.iter().map(|rank| rank.symbol.as_str()).collect();
+        // CHEAP and QUALITY both sum to 3, the higher earnings yield wins
+        assert_eq!(symbols, vec!["CHEAP", "QUALITY", "POOR"]);
+        assert_eq!(ranked[0].rank, 1);
+        assert_eq!(
+            (
+                ranked[1].earnings_yield_rank,
+                ranked[1].return_on_capital_rank
+            ),
+            (2, 1)
+        );
+
+        let top = rank_magic_formula(
+            &[
+                company("A", 1000.0, 0.0, 0.0),
+                company("B", 900.0, 0.0, 0.0),
+            ],
+            &MagicFormulaConfig {
+                top_n: 1,
+                ..MagicFormulaConfig::default()
+            },
+        );
+        assert_eq!(top.len(), 1);
+        assert!(magic_formula_table(&top).contains("A"));
+    }
+}
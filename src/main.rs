@@ -1,11 +1,28 @@
-use anyhow::{Context, Result};
-use financial_analysis::financial_analysis::StockAnalyzer;
+use anyhow::Result;
+use chrono::NaiveDate;
+use financial_analysis::alerts::{send_run_alerts, AlertConfig};
+use financial_analysis::backtest::{
+    run_backtest, BacktestConfig, HistoricalDataSet, RebalanceFrequency,
+};
+use financial_analysis::daemon::Daemon;
+use financial_analysis::financial_analysis::{AnalysisConfig, StockAnalyzer, StockReport};
+use financial_analysis::magic_formula::{magic_formula_table, rank_magic_formula};
+use financial_analysis::net_net::net_net_table;
+use financial_analysis::portfolio::{evaluate_portfolio, Portfolio};
+#[cfg(feature = "tui")]
+use financial_analysis::result_browser::ResultBrowser;
+use financial_analysis::run_diff::diff_runs;
+use financial_analysis::scan::{scan_exchange, scan_exchange_with, Screen};
+use financial_analysis::scoring::ranking_table;
+use financial_analysis::settings::load_settings;
 use financial_analysis::stock_data_fetching::StockInfo;
-use std::env;
-use std::sync::Arc;
+use financial_analysis::storage::{Storage, StorageConfig, WatchlistEntry};
+#[cfg(feature = "tui")]
+use financial_analysis::tui::browse;
+use financial_analysis::watchlist::check_watchlist;
+use structopt::StructOpt;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
-use tokio::sync::Mutex;
 
 async fn save_stocks_to_file(stocks: Vec<StockInfo>, filename: &str) -> Result<()> {
     let mut file = File::create(filename).await?;
@@ -17,50 +34,289 @@ async fn save_stocks_to_file(stocks: Vec<StockInfo>, filename: &str) -> Result<(
     Ok(())
 }
 
+#[derive(StructOpt, Debug)]
+#[structopt(name = "financial-analysis")]
+struct Opt {
+    /// Path to the settings file
+    settings_filename: String,
+
+    /// Stream live quotes for the worthy stocks after the scan
+    #[structopt(long)]
+    monitor: bool,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// List the stored screening runs
+    Runs,
+    /// Compare the pass sets of two stored runs
+    Diff {
+        from_run: i64,
+        to_run: i64,
+        /// Print the diff as JSON instead of a table
+        #[structopt(long)]
+        json: bool,
+    },
+    /// Manage and re-screen the watchlist
+    Watchlist(WatchlistCommand),
+    /// Re-screen the holdings of a portfolio and report unrealised P&L
+    Portfolio {
+        /// Path to the holdings file
+        holdings_filename: String,
+    },
+    /// Replay the screening criteria on the stored snapshots and compare with a benchmark
+    Backtest {
+        /// First rebalancing date, e.g. 2022-01-01
+        #[structopt(long)]
+        from: NaiveDate,
+        #[structopt(long)]
+        to: NaiveDate,
+        /// monthly, quarterly or yearly
+        #[structopt(long, default_value = "quarterly")]
+        rebalance: RebalanceFrequency,
+        #[structopt(long, default_value = "SPY")]
+        benchmark: String,
+    },
+    /// Screen the exchange for stocks below a fraction of their net current asset value
+    NetNets,
+    /// Rank the stocks of a stored run on earnings yield and return on capital, the latest scan by
+    /// default
+    MagicFormula { run_id: Option<i64> },
+    /// Browse the screening results of a stored run, the latest scan by default
+    #[cfg(feature = "tui")]
+    Browse { run_id: Option<i64> },
+    /// Run the jobs scheduled in the settings until SIGTERM or SIGINT, SIGHUP reloads the settings
+    Daemon,
+}
+
+#[derive(StructOpt, Debug)]
+enum WatchlistCommand {
+    /// Add a symbol, or update its note and target price
+    Add {
+        symbol: String,
+        #[structopt(long, default_value = "")]
+        note: String,
+        #[structopt(long)]
+        target_price: Option<f64>,
+    },
+    /// Remove a symbol
+    Remove { symbol: String },
+    /// List the watched symbols
+    List,
+    /// Screen the watched symbols and report changes since the last check
+    Check,
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
-    let settings_filename = env::args()
-        .nth(1)
-        .context("Usage: cargo run <settings_file_path>")?;
-
-    let mut stock_analyzer = StockAnalyzer::new(&settings_filename);
-    let exchange = "US".to_string();
-
-    let stock_list = stock_analyzer.get_exchange_stock_list(&exchange).await?;
-    println!("Stocks on exchange {}: {}", exchange, stock_list.len());
-    
-    let analyzer = Arc::new(Mutex::new(stock_analyzer));
-
-    let results: Vec<_> = futures::future::join_all(stock_list.into_iter().map(|stock| {
-        let analyzer = Arc::clone(&analyzer);
-        tokio::spawn(async move {
-            let mut analyzer = analyzer.lock().await;
-            match analyzer.check_stock(&stock).await {
-                Ok(is_good) => Ok((stock, is_good)),
-                Err(e) => {
-                    println!("Error checking stock {}: {}", stock.symbol, e);
-                    Err(stock)
-                }
+    let opt = Opt::from_args();
+
+    let settings = load_settings(&opt.settings_filename)?;
+    let storage_config = StorageConfig::from_json_value(settings["storage"].clone());
+    let mut storage = Storage::open(&storage_config.database_path)?;
+    let alert_config = AlertConfig::from_json_value(settings["alerts"].clone());
+
+    match &opt.command {
+        None => {
+            let (run_id, mut stock_analyzer) = scan(&opt, &mut storage, &storage_config).await?;
+            send_run_alerts(&alert_config, &storage, run_id).await?;
+            if opt.monitor {
+                let worthy_stocks: Vec<StockInfo> = storage
+                    .run_reports(run_id)?
+                    .into_iter()
+                    .filter(|report| report.passed)
+                    .map(|report| report.stock_info)
+                    .collect();
+                stock_analyzer
+                    .monitor_quotes(&worthy_stocks, &alert_config, &storage)
+                    .await?;
+            }
+            Ok(())
+        }
+        Some(Command::Runs) => {
+            for run in storage.runs()? {
+                println!(
+                    "Run {} ({}): started {} | stocks: {} | passed: {} | failures: {}",
+                    run.id,
+                    run.kind,
+                    run.started_at,
+                    run.num_stocks,
+                    run.num_passed,
+                    run.num_failures
+                );
+            }
+            Ok(())
+        }
+        Some(Command::Diff {
+            from_run,
+            to_run,
+            json,
+        }) => {
+            let diff = diff_runs(&storage, *from_run, *to_run)?;
+            if *json {
+                println!("{}", diff.to_json()?);
+            } else {
+                print!("{}", diff.to_table());
             }
-        })
-    })).await;
-
-    let mut worthy_stocks = Vec::new();
-    let mut shitty_stocks = Vec::new();
-    
-    for result in results {
-        match result {
-            Ok(Ok((stock, true))) => worthy_stocks.push(stock),
-            Ok(Ok((stock, false))) => shitty_stocks.push(stock),
-            Ok(Err(stock)) => shitty_stocks.push(stock),
-            Err(e) => eprintln!("Stock analysis error: {}", e),
+            Ok(())
         }
+        Some(Command::Watchlist(watchlist_command)) => {
+            if let Some(run_id) =
+                run_watchlist_command(watchlist_command, &opt, &mut storage).await?
+            {
+                send_run_alerts(&alert_config, &storage, run_id).await?;
+            }
+            Ok(())
+        }
+        Some(Command::Portfolio { holdings_filename }) => {
+            let portfolio = Portfolio::from_file(holdings_filename)?;
+            let mut stock_analyzer = StockAnalyzer::new(&opt.settings_filename);
+            let evaluation =
+                evaluate_portfolio(&mut stock_analyzer, &mut storage, &portfolio).await?;
+            print!("{}", evaluation.to_table());
+            println!("Saved run {}", evaluation.run_id);
+            send_run_alerts(&alert_config, &storage, evaluation.run_id).await?;
+            Ok(())
+        }
+        Some(Command::Backtest {
+            from,
+            to,
+            rebalance,
+            benchmark,
+        }) => {
+            let config = BacktestConfig {
+                start: *from,
+                end: *to,
+                rebalance: *rebalance,
+                benchmark: benchmark.to_uppercase(),
+            };
+            let mut data = HistoricalDataSet::from_snapshots(&storage)?;
+            let universe = data.symbols();
+            println!("Stocks with stored snapshots: {}", universe.len());
+
+            let mut stock_analyzer = StockAnalyzer::new(&opt.settings_filename);
+            let mut symbols = universe.clone();
+            symbols.push(config.benchmark.clone());
+            data.fetch_filings(&mut stock_analyzer, &universe).await?;
+            data.fetch_prices(&mut stock_analyzer, &symbols, config.start, config.end)
+                .await?;
+
+            let report = run_backtest(&data, &universe, &stock_analyzer.analysis_config, &config)?;
+            print!("{}", report.to_table());
+            Ok(())
+        }
+        Some(Command::NetNets) => {
+            let mut stock_analyzer = StockAnalyzer::new(&opt.settings_filename);
+            let result =
+                scan_exchange_with(&mut stock_analyzer, &mut storage, "US", Screen::NetNet).await?;
+            let net_nets: Vec<StockReport> = storage
+                .run_reports(result.run_id)?
+                .into_iter()
+                .filter(|report| report.passed)
+                .collect();
+            print!("{}", net_net_table(&net_nets));
+            println!("Saved run {}", result.run_id);
+            send_run_alerts(&alert_config, &storage, result.run_id).await?;
+            Ok(())
+        }
+        Some(Command::MagicFormula { run_id }) => {
+            let run = match run_id {
+                Some(run_id) => storage.run(*run_id)?,
+                None => storage.latest_run("scan")?,
+            };
+            let run = run.ok_or_else(|| anyhow::Error::msg("No stored run to rank"))?;
+            let analysis_config = AnalysisConfig::from_json_value(settings["analysis"].clone());
+            let ranked = rank_magic_formula(
+                &storage.run_reports(run.id)?,
+                &analysis_config.magic_formula,
+            );
+            println!("Magic Formula ranking of run {} ({}):", run.id, run.kind);
+            print!("{}", magic_formula_table(&ranked));
+            Ok(())
+        }
+        #[cfg(feature = "tui")]
+        Some(Command::Browse { run_id }) => {
+            let run = match run_id {
+                Some(run_id) => storage.run(*run_id)?,
+                None => storage.latest_run("scan")?,
+            };
+            let run = run.ok_or_else(|| anyhow::Error::msg("No stored run to browse"))?;
+            let browser = ResultBrowser::new(storage.run_reports(run.id)?);
+            browse(browser, &format!("Run {} ({})", run.id, run.kind))
+        }
+        Some(Command::Daemon) => Daemon::new(&opt.settings_filename, storage)?.run().await,
     }
+}
 
-    save_stocks_to_file(shitty_stocks.clone(), "shitty_stocks.txt").await?;
+// Returns the id of the run when the watchlist was screened
+async fn run_watchlist_command(
+    watchlist_command: &WatchlistCommand,
+    opt: &Opt,
+    storage: &mut Storage,
+) -> Result<Option<i64>> {
+    match watchlist_command {
+        WatchlistCommand::Add {
+            symbol,
+            note,
+            target_price,
+        } => {
+            let entry = WatchlistEntry::new(symbol, note, *target_price);
+            storage.add_to_watchlist(&entry)?;
+            println!("Added {} to the watchlist", entry.symbol);
+        }
+        WatchlistCommand::Remove { symbol } => {
+            if storage.remove_from_watchlist(&symbol.to_uppercase())? {
+                println!("Removed {} from the watchlist", symbol.to_uppercase());
+            } else {
+                println!("{} is not on the watchlist", symbol.to_uppercase());
+            }
+        }
+        WatchlistCommand::List => {
+            for entry in storage.watchlist()? {
+                let target_price = entry
+                    .target_price
+                    .map_or("-".to_string(), |price| format!("{price:.2}"));
+                println!(
+                    "{:<8} target price: {:>10} | {}",
+                    entry.symbol, target_price, entry.note
+                );
+            }
+        }
+        WatchlistCommand::Check => {
+            let mut stock_analyzer = StockAnalyzer::new(&opt.settings_filename);
+            let check = check_watchlist(&mut stock_analyzer, storage).await?;
+            print!("{}", check.to_table());
+            println!("Saved run {}", check.run_id);
+            return Ok(Some(check.run_id));
+        }
+    }
+    Ok(None)
+}
 
-    println!("Worthy stocks: {worthy_stocks:?}");
+async fn scan(
+    opt: &Opt,
+    storage: &mut Storage,
+    storage_config: &StorageConfig,
+) -> Result<(i64, StockAnalyzer)> {
+    let mut stock_analyzer = StockAnalyzer::new(&opt.settings_filename);
+    let result = scan_exchange(&mut stock_analyzer, storage, "US").await?;
+    println!(
+        "Saved run {} to {}",
+        result.run_id, storage_config.database_path
+    );
 
-    Ok(())
+    save_stocks_to_file(result.shitty_stocks, "shitty_stocks.txt").await?;
 
-}
\ No newline at end of file
+    println!("Worthy stocks: {:?}", result.worthy_stocks);
+    println!(
+        "Top {} stocks by {}:\n{}",
+        result.ranked_stocks.len(),
+        stock_analyzer.analysis_config.ranking_key,
+        ranking_table(&result.ranked_stocks)
+    );
+
+    Ok((result.run_id, stock_analyzer))
+}
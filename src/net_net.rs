@@ -0,0 +1,235 @@
+use crate::financial_analysis::{CriterionResult, StockReport};
+use crate::scoring::composite_score;
+use crate::stock_data_fetching::{CompanyFinancials, CompanyInformation, CompanyQuote, StockInfo};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NetNetConfig {
+    // Net-nets trade at most at this fraction of the net current asset value per share
+    #[serde(default = "default_ncav_fraction_max")]
+    pub ncav_fraction_max: f64,
+    // Reported next to the NCAV test, Graham's "price < 1.2 * net tangible assets per share"
+    #[serde(default = "default_net_tangible_assets_multiple_max")]
+    pub net_tangible_assets_multiple_max: f64,
+}
+
+fn default_ncav_fraction_max() -> f64 {
+    0.667
+}
+
+fn default_net_tangible_assets_multiple_max() -> f64 {
+    1.2
+}
+
+impl Default for NetNetConfig {
+    fn default() -> Self {
+        Self {
+            ncav_fraction_max: default_ncav_fraction_max(),
+            net_tangible_assets_multiple_max: default_net_tangible_assets_multiple_max(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NetNetValuation {
+    pub year: i32, // Of the reported balance sheet
+    pub ncav_per_share: f64,
+    pub net_tangible_assets_per_share: Option<f64>,
+    pub price_to_ncav: Option<f64>, // None when the NCAV is not positive
+    pub price_to_net_tangible_assets: Option<f64>,
+}
+
+// Net current asset value (current assets - total liabilities) and net tangible assets (total
+// assets - goodwill - intangibles - total liabilities) per share of the latest reported balance
+// sheet, with the current shares outstanding
+pub fn net_net_valuation(
+    financials: &CompanyFinancials,
+    information: &CompanyInformation,
+    quote: &CompanyQuote,
+) -> Result<NetNetValuation> {
+    let latest = financials
+        .reported_statements
+        .first()
+        .ok_or_else(|| Error::msg("No reported balance sheet for the net-net screen"))?;
+    let (current_assets, total_liabilities) =
+        match (latest.total_current_assets, latest.total_liabilities) {
+            (Some(current_assets), Some(total_liabilities)) => (current_assets, total_liabilities),
+            _ => return Err(Error::msg("No current assets or total liabilities")),
+        };
+    if information.shares_outstanding <= 0.0 {
+        return Err(Error::msg("No shares outstanding"));
+    }
+    let per_share = |value: f64| value / information.shares_outstanding;
+    let ncav_per_share = per_share(current_assets - total_liabilities);
+    let net_tangible_assets_per_share = latest.total_assets.map(|total_assets| {
+        per_share(
+            total_assets
+                - latest.goodwill.unwrap_or(0.0)
+                - latest.intangible_assets.unwrap_or(0.0)
+                - total_liabilities,
+        )
+    });
+    let price_to = |value: f64| (value > 0.0).then(|| quote.c / value);
+    Ok(NetNetValuation {
+        year: latest.year,
+        ncav_per_share,
+        net_tangible_assets_per_share,
+        price_to_ncav: price_to(ncav_per_share),
+        price_to_net_tangible_assets: net_tangible_assets_per_share.and_then(price_to),
+    })
+}
+
+fn check_ncav(valuation: &Result<NetNetValuation>, net_net_config: &NetNetConfig) -> Result<bool> {
+    let valuation = match valuation {
+        Ok(valuation) => valuation,
+        Err(error) => return Err(Error::msg(error.to_string())),
+    };
+    println!(
+        "NCAV per share: {:.2} | Price/NCAV: {:?} | Maximum: {:.2}",
+        valuation.ncav_per_share, valuation.price_to_ncav, net_net_config.ncav_fraction_max
+    );
+    Ok(valuation
+        .price_to_ncav
+        .is_some_and(|ratio| ratio <= net_net_config.ncav_fraction_max))
+}
+
+fn check_net_tangible_assets(
+    valuation: &Result<NetNetValuation>,
+    net_net_config: &NetNetConfig,
+) -> Result<bool> {
+    let ratio = valuation
+        .as_ref()
+        .ok()
+        .and_then(|valuation| valuation.price_to_net_tangible_assets)
+        .ok_or_else(|| Error::msg("No positive net tangible assets"))?;
+    Ok(ratio < net_net_config.net_tangible_assets_multiple_max)
+}
+
+// The deep value screen, independent from the defensive criteria of evaluate_stock. Only the NCAV
+// test decides the verdict.
+pub fn evaluate_net_net(
+    stock_info: &StockInfo,
+    financials: CompanyFinancials,
+    information: CompanyInformation,
+    quote: CompanyQuote,
+    net_net_config: &NetNetConfig,
+) -> StockReport {
+    println!("{}: Started net-net check...", stock_info.symbol);
+    let valuation = net_net_valuation(&financials, &information, &quote);
+    let mut report = StockReport::new(stock_info, financials, information, quote);
+
+    report.add_criterion(
+        CriterionResult::new(
+            "price_to_ncav",
+            check_ncav(&valuation, net_net_config),
+            true,
+            valuation
+                .as_ref()
+                .ok()
+                .and_then(|valuation| valuation.price_to_ncav),
+        )
+        .with_limits(None, Some(net_net_config.ncav_fraction_max)),
+    );
+    report.add_criterion(
+        CriterionResult::new(
+            "price_to_net_tangible_assets",
+            check_net_tangible_assets(&valuation, net_net_config),
+            false,
+            valuation
+                .as_ref()
+                .ok()
+                .and_then(|valuation| valuation.price_to_net_tangible_assets),
+        )
+        .with_limits(None, Some(net_net_config.net_tangible_assets_multiple_max)),
+    );
+    report.score = composite_score(&report.criteria, &HashMap::new());
+    report.net_net = valuation.ok();
+
+    if report.passed {
+        println!("{}: NET-NET", stock_info.symbol);
+    }
+    report
+}
+
+pub fn net_net_table(reports: &[StockReport]) -> String {
+    let mut table = format!(
+        "{:<10}{:>10}{:>14}{:>12}{:>12}\n",
+        "SYMBOL", "PRICE", "NCAV/SHARE", "PRICE/NCAV", "PRICE/NTA"
+    );
+    let format_ratio =
+        |ratio: Option<f64>| ratio.map_or("-".to_string(), |ratio| format!("{ratio:.2}"));
+    for report in reports {
+        if let Some(valuation) = &report.net_net {
+            table += &format!(
+                "{:<10}{:>10.2}{:>14.2}{:>12}{:>12}\n",
+                report.stock_info.symbol,
+                report.quote.c,
+                valuation.ncav_per_share,
+                format_ratio(valuation.price_to_ncav),
+                format_ratio(valuation.price_to_net_tangible_assets)
+            );
+        }
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fundamentals_history::ReportedStatements;
+    use crate::test_fixtures::{financials, information, quote, stock_info};
+
+    #[test]
+    fn test_net_net() {
+        // NCAV (500 - 300) / 10 = 20 per share, net tangible assets (900 - 100 - 300) / 10 = 50
+        let mut financials = financials(8.0);
+        financials.reported_statements = vec![ReportedStatements {
+            year: 2022,
+            total_assets: Some(900.0),
+            total_current_assets: Some(500.0),
+            total_liabilities: Some(300.0),
+            goodwill: Some(100.0),
+            ..ReportedStatements::default()
+        }];
+        let mut information = information("NET");
+        information.shares_outstanding = 10.0;
+        let net_net_config = NetNetConfig::default();
+
+        let report = evaluate_net_net(
+            &stock_info("NET"),
+            financials.clone(),
+            information.clone(),
+            quote(12.0),
+            &net_net_config,
+        );
+        let valuation = report.net_net.as_ref().unwrap();
+        assert_eq!(valuation.ncav_per_share, 20.0);
+        assert_eq!(valuation.net_tangible_assets_per_share, Some(50.0));
+        assert_eq!(valuation.price_to_ncav, Some(0.6));
+        assert!(report.passed);
+        assert!(net_net_table(&[report]).contains("NET"));
+
+        let report = evaluate_net_net(
+            &stock_info("NET"),
+            financials.clone(),
+            information.clone(),
+            quote(15.0),
+            &net_net_config,
+        );
+        assert!(!report.passed);
+
+        // A negative NCAV never qualifies
+        financials.reported_statements[0].total_liabilities = Some(600.0);
+        let report = evaluate_net_net(
+            &stock_info("NET"),
+            financials,
+            information,
+            quote(1.0),
+            &net_net_config,
+        );
+        assert_eq!(report.net_net.as_ref().unwrap().price_to_ncav, None);
+        assert!(!report.passed);
+    }
+}
@@ -0,0 +1,228 @@
+use crate::fundamentals_history::ReportedStatements;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PiotroskiConfig {
+    #[serde(default = "default_min_score")]
+    pub min_score: u32, // Out of 9
+    // Whether a lower score fails the stock, otherwise the score is only reported
+    #[serde(default)]
+    pub required: bool,
+}
+
+fn default_min_score() -> u32 {
+    7
+}
+
+impl Default for PiotroskiConfig {
+    fn default() -> Self {
+        Self {
+            min_score: default_min_score(),
+            required: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FScoreSignal {
+    pub name: String,
+    pub passed: bool,
+    pub missing_data: bool, // Signals without the items they need count as failed
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PiotroskiFScore {
+    pub year: i32, // The latest reported year
+    pub score: u32,
+    pub signals: Vec<FScoreSignal>,
+}
+
+impl PiotroskiFScore {
+    pub fn failed_signals(&self) -> Vec<&str> {
+        self.signals
+            .iter()
+            .filter(|signal| !signal.passed)
+            .map(|signal| signal.name.as_str())
+            .collect()
+    }
+}
+
+// Total assets at the start of the year, the year end assets when the prior year is missing
+fn beginning_assets(statements: &[ReportedStatements], year: usize) -> Option<f64> {
+    statements
+        .get(year + 1)
+        .and_then(|prior| prior.total_assets)
+        .or(statements[year].total_assets)
+}
+
+fn average_assets(statements: &[ReportedStatements], year: usize) -> Option<f64> {
+    Some((statements[year].total_assets? + beginning_assets(statements, year)?) / 2.0)
+}
+
+fn return_on_assets(statements: &[ReportedStatements], year: usize) -> Option<f64> {
+    Some(statements[year].net_income? / beginning_assets(statements, year)?)
+}
+
+// Missing long term debt is taken as no long term debt
+fn leverage(statements: &[ReportedStatements], year: usize) -> Option<f64> {
+    Some(statements[year].long_term_debt.unwrap_or(0.0) / average_assets(statements, year)?)
+}
+
+fn current_ratio(statements: &ReportedStatements) -> Option<f64> {
+    Some(statements.total_current_assets? / statements.total_current_liabilities?)
+}
+
+fn gross_margin(statements: &ReportedStatements) -> Option<f64> {
+    Some(statements.gross_profit? / statements.revenue?)
+}
+
+fn asset_turnover(statements: &[ReportedStatements], year: usize) -> Option<f64> {
+    Some(statements[year].revenue? / beginning_assets(statements, year)?)
+}
+
+// Piotroski's nine binary signals of the latest year against the year before, the statements are
+// ordered newest first
+pub fn piotroski_f_score(statements: &[ReportedStatements]) -> Result<PiotroskiFScore> {
+    if statements.len() < 2 {
+        return Err(Error::msg(
+            "Less than two years of reported statements for the Piotroski F-score",
+        ));
+    }
+    let (current, prior) = (&statements[0], &statements[1]);
+    if current.net_income.is_none() || current.total_assets.is_none() {
+        return Err(Error::msg("No net income or total assets"));
+    }
+
+    let checks = [
+        // Profitability
+        (
+            "positive_return_on_assets",
+            return_on_assets(statements, 0).map(|roa| roa > 0.0),
+        ),
+        (
+            "positive_operating_cash_flow",
+            current.operating_cash_flow.map(|cfo| cfo > 0.0),
+        ),
+        (
+            "increasing_return_on_assets",
+            return_on_assets(statements, 0)
+                .zip(return_on_assets(statements, 1))
+                .map(|(roa, prior_roa)| roa > prior_roa),
+        ),
+        (
+            "cash_flow_above_net_income",
+            current
+                .operating_cash_flow
+                .zip(current.net_income)
+                .map(|(cfo, net_income)| cfo > net_income),
+        ),
+        // Leverage, liquidity and source of funds
+        (
+            "decreasing_leverage",
+            leverage(statements, 0)
+                .zip(leverage(statements, 1))
+                .map(|(leverage, prior_leverage)| leverage <= prior_leverage),
+        ),
+        (
+            "increasing_current_ratio",
+            current_ratio(current)
+                .zip(current_ratio(prior))
+                .map(|(ratio, prior_ratio)| ratio > prior_ratio),
+        ),
+        (
+            "no_dilution",
+            current
+                .shares_outstanding
+                .zip(prior.shares_outstanding)
+                .map(|(shares, prior_shares)| shares <= prior_shares),
+        ),
+        // Operating efficiency
+        (
+            "increasing_gross_margin",
+            gross_margin(current)
+                .zip(gross_margin(prior))
+                .map(|(margin, prior_margin)| margin > prior_margin),
+        ),
+        (
+            "increasing_asset_turnover",
+            asset_turnover(statements, 0)
+                .zip(asset_turnover(statements, 1))
+                .map(|(turnover, prior_turnover)| turnover > prior_turnover),
+        ),
+    ];
+
+    let signals: Vec<FScoreSignal> = checks
+        .into_iter()
+        .map(|(name, check)| FScoreSignal {
+            name: name.to_string(),
+            passed: check == Some(true),
+            missing_data: check.is_none(),
+        })
+        .collect();
+    Ok(PiotroskiFScore {
+        year: current.year,
+        score: signals.iter().filter(|signal| signal.passed).count() as u32,
+        signals,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn statements(
+        year: i32,
+        total_assets: f64,
+        net_income: f64,
+        revenue: f64,
+        gross_profit: f64,
+    ) -> ReportedStatements {
+        ReportedStatements {
+            year,
+            total_assets: Some(total_assets),
+            total_current_assets: Some(0.4 * total_assets),
+            total_current_liabilities: Some(0.2 * total_assets),
+            long_term_debt: Some(0.3 * total_assets),
+            revenue: Some(revenue),
+            gross_profit: Some(gross_profit),
+            net_income: Some(net_income),
+            operating_cash_flow: Some(1.5 * net_income),
+            shares_outstanding: Some(100.0),
+            ..ReportedStatements::default()
+        }
+    }
+
+    #[test]
+    fn test_piotroski_f_score() -> Result<()> {
+        let mut current = statements(2022, 1000.0, 80.0, 1200.0, 480.0);
+        current.long_term_debt = Some(200.0);
+        current.total_current_liabilities = Some(150.0);
+        current.shares_outstanding = Some(110.0);
+        let prior = statements(2021, 1000.0, 50.0, 1000.0, 350.0);
+        let before_prior = statements(2020, 1000.0, 40.0, 900.0, 300.0);
+
+        let f_score = piotroski_f_score(&[current.clone(), prior.clone(), before_prior])?;
+        assert_eq!(f_score.year, 2022);
+        assert_eq!(f_score.score, 8);
+        assert_eq!(f_score.failed_signals(), vec!["no_dilution"]);
+
+        // Without the year before the prior year the beginning assets fall back to the year end
+        current.operating_cash_flow = None;
+        let f_score = piotroski_f_score(&[current, prior.clone()])?;
+        assert_eq!(f_score.score, 6);
+        let missing: Vec<&str> = f_score
+            .signals
+            .iter()
+            .filter(|signal| signal.missing_data)
+            .map(|signal| signal.name.as_str())
+            .collect();
+        assert_eq!(
+            missing,
+            vec!["positive_operating_cash_flow", "cash_flow_above_net_income"]
+        );
+
+        assert!(piotroski_f_score(&[prior]).is_err());
+        Ok(())
+    }
+}
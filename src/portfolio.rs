@@ -0,0 +1,223 @@
+use crate::financial_analysis::{StockAnalyzer, StockReport};
+use crate::stock_data_fetching::StockInfo;
+use crate::storage::Storage;
+use anyhow::{Error, Result};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Holding {
+    pub symbol: String,
+    pub quantity: f64,
+    pub cost_basis: f64, // Per share, in the currency of the stock
+    pub purchase_date: NaiveDate,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Portfolio {
+    pub holdings: Vec<Holding>,
+}
+
+impl Portfolio {
+    pub fn from_file(filename: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(filename)
+            .map_err(|error| Error::msg(format!("Could not read {filename}: {error}")))?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PositionEvaluation {
+    pub holding: Holding,
+    pub price: f64,
+    pub market_value: f64,
+    pub unrealised_pnl: f64,
+    pub unrealised_pnl_percent: f64,
+    pub failing_criteria: Vec<String>, // Descriptions of the required criteria that now fail
+    pub intrinsic_value: Option<f64>,
+    pub above_intrinsic_value: bool,
+}
+
+impl PositionEvaluation {
+    // The position no longer meets the buy criteria or is priced above its estimated value
+    pub fn should_review(&self) -> bool {
+        !self.failing_criteria.is_empty() || self.above_intrinsic_value
+    }
+}
+
+pub fn evaluate_position(holding: &Holding, report: &StockReport) -> PositionEvaluation {
+    let price = report.quote.c;
+    let cost = holding.quantity * holding.cost_basis;
+    let market_value = holding.quantity * price;
+    let unrealised_pnl = market_value - cost;
+    let unrealised_pnl_percent = if cost != 0.0 {
+        100.0 * unrealised_pnl / cost
+    } else {
+        0.0
+    };
+
+    PositionEvaluation {
+        holding: holding.clone(),
+        price,
+        market_value,
+        unrealised_pnl,
+        unrealised_pnl_percent,
+        failing_criteria: report
+            .failed_criteria()
+            .iter()
+            .map(|criterion| criterion.describe())
+            .collect(),
+        intrinsic_value: report.intrinsic_value,
+        above_intrinsic_value: report
+            .intrinsic_value
+            .is_some_and(|intrinsic_value| price > intrinsic_value),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PortfolioEvaluation {
+    pub run_id: i64,
+    pub positions: Vec<PositionEvaluation>,
+    pub errors: Vec<(String, String)>, // Symbol and error for holdings that could not be evaluated
+}
+
+impl PortfolioEvaluation {
+    pub fn total_market_value(&self) -> f64 {
+        self.positions
+            .iter()
+            .map(|position| position.market_value)
+            .sum()
+    }
+
+    pub fn total_unrealised_pnl(&self) -> f64 {
+        self.positions
+            .iter()
+            .map(|position| position.unrealised_pnl)
+            .sum()
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = format!(
+            "{:<8} {:>10} {:>10} {:>10} {:>12} {:>12} {:>8}  {}\n",
+            "SYMBOL", "QUANTITY", "COST", "PRICE", "VALUE", "P&L", "P&L %", "FLAGS"
+        );
+        for position in &self.positions {
+            let mut flags = position.failing_criteria.clone();
+            if position.above_intrinsic_value {
+                flags.push(format!(
+                    "price above intrinsic value {:.2}",
+                    position.intrinsic_value.unwrap_or_default()
+                ));
+            }
+            table += &format!(
+                "{:<8} {:>10.2} {:>10.2} {:>10.2} {:>12.2} {:>12.2} {:>8.1}  {}\n",
+                position.holding.symbol,
+                position.holding.quantity,
+                position.holding.cost_basis,
+                position.price,
+                position.market_value,
+                position.unrealised_pnl,
+                position.unrealised_pnl_percent,
+                flags.join(", "),
+            );
+        }
+        for (symbol, error) in &self.errors {
+            table += &format!("{symbol:<8} Could not evaluate: {error}\n");
+        }
+        table += &format!(
+            "\nTotal value: {:.2} | Total unrealised P&L: {:.2}\n",
+            self.total_market_value(),
+            self.total_unrealised_pnl()
+        );
+        table
+    }
+}
+
+// Re-screens every holding and stores the results as a "portfolio" run
+pub async fn evaluate_portfolio(
+    analyzer: &mut StockAnalyzer,
+    storage: &mut Storage,
+    portfolio: &Portfolio,
+) -> Result<PortfolioEvaluation> {
+    let run_id = storage.begin_run("portfolio", &analyzer.analysis_config)?;
+    let mut positions = Vec::new();
+    let mut errors = Vec::new();
+    for holding in &portfolio.holdings {
+        match analyzer
+            .analyze_stock(&StockInfo::from_symbol(&holding.symbol))
+            .await
+        {
+            Ok(report) => {
+                storage.save_report(run_id, &report)?;
+                positions.push(evaluate_position(holding, &report));
+            }
+            Err(error) => {
+                storage.save_failure(run_id, &holding.symbol, &error.to_string())?;
+                errors.push((holding.symbol.clone(), error.to_string()));
+            }
+        }
+    }
+    storage.finish_run(run_id)?;
+
+    Ok(PortfolioEvaluation {
+        run_id,
+        positions,
+        errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::report;
+
+    fn test_holding(symbol: &str, cost_basis: f64) -> Holding {
+        Holding {
+            symbol: symbol.to_string(),
+            quantity: 10.0,
+            cost_basis,
+            purchase_date: NaiveDate::from_ymd_opt(2021, 3, 1).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_holdings_file() -> Result<()> {
+        let portfolio = Portfolio::from_file("config/holdings_example.json")?;
+        assert!(!portfolio.holdings.is_empty());
+        assert!(Portfolio::from_file("config/no_such_file.json").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_evaluate_position() {
+        let position = evaluate_position(&test_holding("GOOD", 25.0), &report("GOOD", 10.0, 30.0));
+        assert_eq!(position.market_value, 300.0);
+        assert_eq!(position.unrealised_pnl, 50.0);
+        assert_eq!(position.unrealised_pnl_percent, 20.0);
+        assert!(position.failing_criteria.is_empty());
+        assert!(!position.should_review());
+
+        let position = evaluate_position(&test_holding("DEAR", 25.0), &report("DEAR", 25.1, 20.0));
+        assert_eq!(position.unrealised_pnl, -50.0);
+        assert_eq!(position.failing_criteria, vec!["pe 25.10 above 22.50"]);
+        assert!(position.should_review());
+    }
+
+    #[test]
+    fn test_position_above_intrinsic_value() {
+        // V = 3 * (8.5 + 14) * 4.4 / 3.36 = 88.39
+        let position = evaluate_position(&test_holding("HIGH", 25.0), &report("HIGH", 10.0, 90.0));
+        assert!(position.above_intrinsic_value);
+        assert!(position.should_review());
+
+        let evaluation = PortfolioEvaluation {
+            run_id: 1,
+            positions: vec![position],
+            errors: vec![("GONE".to_string(), "No balance sheet".to_string())],
+        };
+        assert_eq!(evaluation.total_market_value(), 900.0);
+        let table = evaluation.to_table();
+        assert!(table.contains("price above intrinsic value 88.39"));
+        assert!(table.contains("GONE     Could not evaluate"));
+    }
+}
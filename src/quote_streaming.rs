@@ -0,0 +1,383 @@
+use crate::financial_analysis::{
+    check_working_capital, graham_intrinsic_value, margin_of_safety, AnalysisConfig, StockReport,
+};
+use crate::stock_data_fetching::{CompanyFinancials, CompanyInformation, CompanyQuote, StockInfo};
+use anyhow::{Error, Result};
+use futures::{SinkExt, StreamExt};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trade {
+    #[serde(rename = "s")]
+    pub symbol: String,
+    #[serde(rename = "p")]
+    pub price: f64,
+    #[serde(rename = "t")]
+    pub timestamp: i64, // In milliseconds
+    #[serde(rename = "v")]
+    pub volume: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct StreamMessage {
+    #[serde(rename = "type")]
+    message_type: String,
+    #[serde(default)]
+    data: Vec<Trade>,
+    #[serde(default)]
+    msg: String,
+}
+
+// Finnhub trade feed, see https://finnhub.io/docs/api/websocket-trades
+pub struct QuoteStream {
+    socket: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+impl QuoteStream {
+    pub async fn connect(websocket_url: &str, api_key: &str) -> Result<Self, Error> {
+        let url = Url::parse_with_params(websocket_url, &[("token", api_key)])?;
+        let (socket, _response) = connect_async(url).await?;
+        Ok(Self { socket })
+    }
+
+    pub async fn subscribe(&mut self, symbol: &str) -> Result<(), Error> {
+        let message = serde_json::json!({ "type": "subscribe", "symbol": symbol });
+        self.socket.send(Message::Text(message.to_string())).await?;
+        Ok(())
+    }
+
+    pub async fn unsubscribe(&mut self, symbol: &str) -> Result<(), Error> {
+        let message = serde_json::json!({ "type": "unsubscribe", "symbol": symbol });
+        self.socket.send(Message::Text(message.to_string())).await?;
+        Ok(())
+    }
+
+    // Waits for the next batch of trades, returns None when the server closes the stream
+    pub async fn next_trades(&mut self) -> Result<Option<Vec<Trade>>, Error> {
+        while let Some(message) = self.socket.next().await {
+            let text = match message? {
+                Message::Text(text) => text,
+                Message::Close(_) => return Ok(None),
+                _ => continue,
+            };
+            let stream_message: StreamMessage = serde_json::from_str(&text)?;
+            match stream_message.message_type.as_str() {
+                "trade" => return Ok(Some(stream_message.data)),
+                "error" => {
+                    return Err(Error::msg(format!(
+                        "Quote stream error: {}",
+                        stream_message.msg
+                    )))
+                }
+                _ => continue, // pings
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct QuoteBook {
+    quotes: HashMap<String, CompanyQuote>,
+}
+
+impl QuoteBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, symbol: &str, quote: CompanyQuote) {
+        self.quotes.insert(symbol.to_string(), quote);
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<&CompanyQuote> {
+        self.quotes.get(symbol)
+    }
+
+    pub fn len(&self) -> usize {
+        self.quotes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.quotes.is_empty()
+    }
+
+    pub fn apply_trade(&mut self, trade: &Trade) -> CompanyQuote {
+        let quote = self
+            .quotes
+            .entry(trade.symbol.clone())
+            .or_insert(CompanyQuote {
+                c: trade.price,
+                h: trade.price,
+                l: trade.price,
+                o: trade.price,
+                pc: trade.price,
+                t: 0,
+            });
+        quote.c = trade.price;
+        quote.h = quote.h.max(trade.price);
+        quote.l = quote.l.min(trade.price);
+        quote.t = (trade.timestamp / 1000) as i128;
+        *quote
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct QuoteCheck {
+    pub symbol: String,
+    pub price: f64,
+    pub working_capital_good: bool,
+    pub intrinsic_value: Option<f64>,
+    pub margin_of_safety: Option<f64>,
+}
+
+// Keeps the fundamentals of the watched stocks so the price dependent checks can be re-evaluated
+// on every trade without new REST calls
+#[derive(Debug, Default)]
+pub struct QuoteMonitor {
+    book: QuoteBook,
+    fundamentals: HashMap<String, (CompanyFinancials, CompanyInformation)>,
+}
+
+impl QuoteMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn watch(
+        &mut self,
+        symbol: &str,
+        financials: CompanyFinancials,
+        information: CompanyInformation,
+        quote: CompanyQuote,
+    ) {
+        self.book.insert(symbol, quote);
+        self.fundamentals
+            .insert(symbol.to_string(), (financials, information));
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        self.fundamentals.keys().cloned().collect()
+    }
+
+    pub fn book(&self) -> &QuoteBook {
+        &self.book
+    }
+
+    pub fn apply_trade(
+        &mut self,
+        trade: &Trade,
+        analysis_config: &AnalysisConfig,
+    ) -> Option<QuoteCheck> {
+        let (financials, information) = self.fundamentals.get(&trade.symbol)?;
+        let quote = self.book.apply_trade(trade);
+        let analysis_config = &analysis_config.for_company(information);
+
+        let working_capital_good =
+            check_working_capital(financials, information, &quote).unwrap_or(false);
+        let intrinsic_value = graham_intrinsic_value(financials, information, analysis_config).ok();
+        let margin_of_safety =
+            intrinsic_value.and_then(|intrinsic_value| margin_of_safety(intrinsic_value, quote.c));
+
+        Some(QuoteCheck {
+            symbol: trade.symbol.clone(),
+            price: quote.c,
+            working_capital_good,
+            intrinsic_value,
+            margin_of_safety,
+        })
+    }
+
+    // The fundamentals and latest quote of a checked stock, for the price based alert rules. The
+    // criteria are not evaluated.
+    pub fn report(&self, check: &QuoteCheck) -> Option<StockReport> {
+        let (financials, information) = self.fundamentals.get(&check.symbol)?;
+        let quote = self.book.get(&check.symbol)?;
+        let mut report = StockReport::new(
+            &StockInfo::from_symbol(&check.symbol),
+            financials.clone(),
+            information.clone(),
+            *quote,
+        );
+        report.intrinsic_value = check.intrinsic_value;
+        report.margin_of_safety = check.margin_of_safety;
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    fn test_analysis_config() -> AnalysisConfig {
+        AnalysisConfig::from_json_value(serde_json::json!({
+            "pe_limits": [2.0, 22.5],
+            "pb_limits": [0.4, 5.0],
+            "earnings_growth_5y_min": 6.0,
+            "dividend_per_share_min": 0.1,
+            "dividend_growth_5y_min": 5.0,
+            "current_ratio_min": 1.5,
+            "debt_equity_max": 2.0,
+            "market_cap_min": 20e3,
+            "nor_aaa_10y_bond_yield": 0.0295,
+            "us_aaa_10y_bond_yield": 0.0336
+        }))
+    }
+
+    fn test_fundamentals() -> (CompanyFinancials, CompanyInformation) {
+        let financials: CompanyFinancials = serde_json::from_value(serde_json::json!({
+            "earnings_per_share": 2.0,
+            "earnings_growth_5_yr_avg": 5.0,
+            "total_current_assets": 1000.0,
+            "total_current_liabilities": 400.0,
+            "total_current_long_term_debt": 50.0
+        }))
+        .unwrap();
+        let information = CompanyInformation {
+            name: "Test".to_string(),
+            ticker: "TEST".to_string(),
+            exchange: "US".to_string(),
+            currency: "USD".to_string(),
+            country: "US".to_string(),
+            industry: "Retail".to_string(),
+            market_cap: 1000.0,
+            shares_outstanding: 100.0,
+            ipo: "2000-01-01".to_string(),
+            weburl: String::new(),
+            finnhub_industry: "Retail".to_string(),
+        };
+        (financials, information)
+    }
+
+    fn test_trade(symbol: &str, price: f64) -> Trade {
+        Trade {
+            symbol: symbol.to_string(),
+            price,
+            timestamp: 1_675_000_000_000,
+            volume: 10.0,
+        }
+    }
+
+    // Accepts a single client, reports its first message and replies with the given messages
+    async fn spawn_stand_in_server(replies: Vec<String>) -> (String, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (first_message_sender, first_message_receiver) = oneshot::channel();
+        tokio::spawn(async move {
+            let (tcp_stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(tcp_stream).await.unwrap();
+            if let Some(Ok(Message::Text(text))) = socket.next().await {
+                first_message_sender.send(text).unwrap();
+            }
+            for reply in replies {
+                socket.send(Message::Text(reply)).await.unwrap();
+            }
+            socket.close(None).await.unwrap();
+        });
+        (format!("ws://{address}"), first_message_receiver)
+    }
+
+    #[test]
+    fn test_quote_book_tracks_high_and_low() {
+        let mut book = QuoteBook::new();
+        book.apply_trade(&test_trade("TEST", 10.0));
+        book.apply_trade(&test_trade("TEST", 12.0));
+        let quote = book.apply_trade(&test_trade("TEST", 9.0));
+
+        assert_eq!(book.len(), 1);
+        assert_eq!(quote.c, 9.0);
+        assert_eq!(quote.h, 12.0);
+        assert_eq!(quote.l, 9.0);
+        assert_eq!(quote.t, 1_675_000_000);
+    }
+
+    #[test]
+    fn test_quote_monitor_reevaluates_checks() {
+        let (financials, information) = test_fundamentals();
+        let quote = CompanyQuote {
+            c: 20.0,
+            h: 20.0,
+            l: 20.0,
+            o: 20.0,
+            pc: 20.0,
+            t: 0,
+        };
+        let analysis_config = test_analysis_config();
+        let mut monitor = QuoteMonitor::new();
+        monitor.watch("TEST", financials, information, quote);
+
+        // Working capital per share is 6, so the price must be at most 9
+        let check = monitor
+            .apply_trade(&test_trade("TEST", 8.0), &analysis_config)
+            .unwrap();
+        assert!(check.working_capital_good);
+        let check = monitor
+            .apply_trade(&test_trade("TEST", 10.0), &analysis_config)
+            .unwrap();
+        assert!(!check.working_capital_good);
+
+        // V = 2 * (8.5 + 10) * 4.4 / 3.36 = 48.45
+        let margin = check.margin_of_safety.unwrap();
+        assert!((margin - (1.0 - 10.0 / 48.452)).abs() < 1e-3);
+        let report = monitor.report(&check).unwrap();
+        assert_eq!(report.quote.c, 10.0);
+        assert_eq!(report.margin_of_safety, check.margin_of_safety);
+
+        assert!(monitor
+            .apply_trade(&test_trade("OTHER", 8.0), &analysis_config)
+            .is_none());
+
+        // Negative earnings have no intrinsic value to measure a margin against
+        let (mut financials, information) = test_fundamentals();
+        financials.earnings_per_share = Some(-1.0);
+        monitor.watch("LOSS", financials, information, quote);
+        let check = monitor
+            .apply_trade(&test_trade("LOSS", 8.0), &analysis_config)
+            .unwrap();
+        assert_eq!(check.margin_of_safety, None);
+    }
+
+    #[tokio::test]
+    async fn test_quote_stream_against_stand_in_server() -> Result<()> {
+        let replies = vec![
+            r#"{"type":"ping"}"#.to_string(),
+            r#"{"type":"trade","data":[{"s":"TEST","p":8.5,"t":1675000000000,"v":100,"c":null}]}"#
+                .to_string(),
+        ];
+        let (url, first_message) = spawn_stand_in_server(replies).await;
+
+        let mut stream = QuoteStream::connect(&url, "test-key").await?;
+        stream.subscribe("TEST").await?;
+
+        let subscription: serde_json::Value = serde_json::from_str(&first_message.await?)?;
+        assert_eq!(subscription["type"], "subscribe");
+        assert_eq!(subscription["symbol"], "TEST");
+
+        let trades = stream.next_trades().await?.unwrap();
+        assert_eq!(trades.len(), 1);
+        assert_eq!(trades[0].symbol, "TEST");
+        assert_eq!(trades[0].price, 8.5);
+
+        assert!(stream.next_trades().await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_quote_stream_error_message() -> Result<()> {
+        let replies = vec![r#"{"type":"error","msg":"Invalid token"}"#.to_string()];
+        let (url, _first_message) = spawn_stand_in_server(replies).await;
+
+        let mut stream = QuoteStream::connect(&url, "bad-key").await?;
+        stream.subscribe("TEST").await?;
+        let error = stream.next_trades().await.unwrap_err();
+        assert!(error.to_string().contains("Invalid token"));
+        Ok(())
+    }
+}
@@ -0,0 +1,281 @@
+use crate::financial_analysis::StockReport;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    Symbol,
+    Pe,
+    Pb,
+    DebtEquity,
+    MarginOfSafety,
+    GrowthGap,
+}
+
+impl SortColumn {
+    pub const ALL: [SortColumn; 6] = [
+        SortColumn::Symbol,
+        SortColumn::Pe,
+        SortColumn::Pb,
+        SortColumn::DebtEquity,
+        SortColumn::MarginOfSafety,
+        SortColumn::GrowthGap,
+    ];
+
+    pub fn title(&self) -> &'static str {
+        match self {
+            SortColumn::Symbol => "SYMBOL",
+            SortColumn::Pe => "P/E",
+            SortColumn::Pb => "P/B",
+            SortColumn::DebtEquity => "DEBT/EQUITY",
+            SortColumn::MarginOfSafety => "MARGIN OF SAFETY",
+            SortColumn::GrowthGap => "GROWTH GAP",
+        }
+    }
+
+    // Stocks without a value sort last
+    pub fn value(&self, report: &StockReport) -> Option<f64> {
+        let criterion_value = |name| report.criterion(name).and_then(|criterion| criterion.value);
+        match self {
+            SortColumn::Symbol => None,
+            SortColumn::Pe => criterion_value("pe"),
+            SortColumn::Pb => criterion_value("pb"),
+            SortColumn::DebtEquity => criterion_value("debt_equity"),
+            SortColumn::MarginOfSafety => report.margin_of_safety,
+            SortColumn::GrowthGap => report
+                .implied_growth
+                .as_ref()
+                .and_then(|implied| implied.growth_gap),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PassFilter {
+    Any,
+    Passed,
+    Failed,
+}
+
+impl PassFilter {
+    pub fn next(&self) -> Self {
+        match self {
+            PassFilter::Any => PassFilter::Passed,
+            PassFilter::Passed => PassFilter::Failed,
+            PassFilter::Failed => PassFilter::Any,
+        }
+    }
+
+    fn accepts(&self, passed: Option<bool>) -> bool {
+        match self {
+            PassFilter::Any => true,
+            PassFilter::Passed => passed == Some(true),
+            PassFilter::Failed => passed == Some(false),
+        }
+    }
+}
+
+// Screening results of a run with the sorting, filtering and selection of the terminal UI
+pub struct ResultBrowser {
+    reports: Vec<StockReport>,
+    criteria: Vec<String>, // Criterion names in the order of the reports
+    pub sort_column: SortColumn,
+    pub ascending: bool,
+    pub total_filter: PassFilter,
+    pub criterion_filters: Vec<PassFilter>, // Parallel to criteria
+    visible: Vec<usize>,                    // Indices into reports after filtering and sorting
+    selected: usize,                        // Index into visible
+}
+
+impl ResultBrowser {
+    pub fn new(reports: Vec<StockReport>) -> Self {
+        let mut criteria: Vec<String> = Vec::new();
+        for report in &reports {
+            for criterion in &report.criteria {
+                if !criteria.contains(&criterion.name) {
+                    criteria.push(criterion.name.clone());
+                }
+            }
+        }
+        let mut browser = Self {
+            reports,
+            criterion_filters: vec![PassFilter::Any; criteria.len()],
+            criteria,
+            sort_column: SortColumn::Symbol,
+            ascending: true,
+            total_filter: PassFilter::Any,
+            visible: Vec::new(),
+            selected: 0,
+        };
+        browser.refresh();
+        browser
+    }
+
+    pub fn criteria(&self) -> &[String] {
+        &self.criteria
+    }
+
+    pub fn num_reports(&self) -> usize {
+        self.reports.len()
+    }
+
+    pub fn visible_reports(&self) -> Vec<&StockReport> {
+        self.visible
+            .iter()
+            .map(|&index| &self.reports[index])
+            .collect()
+    }
+
+    pub fn selected_index(&self) -> Option<usize> {
+        (!self.visible.is_empty()).then_some(self.selected)
+    }
+
+    pub fn selected_report(&self) -> Option<&StockReport> {
+        self.visible
+            .get(self.selected)
+            .map(|&index| &self.reports[index])
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.visible.len() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    // Sorting by the current column again reverses the order
+    pub fn sort_by(&mut self, column: SortColumn) {
+        if self.sort_column == column {
+            self.ascending = !self.ascending;
+        } else {
+            self.sort_column = column;
+            self.ascending = true;
+        }
+        self.refresh();
+    }
+
+    pub fn cycle_total_filter(&mut self) {
+        self.total_filter = self.total_filter.next();
+        self.refresh();
+    }
+
+    pub fn cycle_criterion_filter(&mut self, criterion_index: usize) {
+        if let Some(filter) = self.criterion_filters.get_mut(criterion_index) {
+            *filter = filter.next();
+            self.refresh();
+        }
+    }
+
+    fn accepts(&self, report: &StockReport) -> bool {
+        self.total_filter.accepts(Some(report.passed))
+            && self
+                .criteria
+                .iter()
+                .zip(&self.criterion_filters)
+                .all(|(name, filter)| {
+                    filter.accepts(report.criterion(name).map(|criterion| criterion.passed))
+                })
+    }
+
+    // Keeps the selected stock selected when it is still visible
+    fn refresh(&mut self) {
+        let selected_report = self.visible.get(self.selected).copied();
+
+        let mut visible: Vec<usize> = (0..self.reports.len())
+            .filter(|&index| self.accepts(&self.reports[index]))
+            .collect();
+        let column = self.sort_column;
+        visible.sort_by(|&a, &b| {
+            let (a, b) = (&self.reports[a], &self.reports[b]);
+            let ordering = match column {
+                SortColumn::Symbol => a.stock_info.symbol.cmp(&b.stock_info.symbol),
+                _ => match (column.value(a), column.value(b)) {
+                    (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                    (Some(_), None) => return Ordering::Less,
+                    (None, Some(_)) => return Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                },
+            };
+            if self.ascending {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+
+        self.selected = selected_report
+            .and_then(|report| visible.iter().position(|&index| index == report))
+            .unwrap_or(0);
+        self.visible = visible;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::report;
+
+    fn symbols(browser: &ResultBrowser) -> Vec<&str> {
+        browser
+            .visible_reports()
+            .iter()
+            .map(|report| report.stock_info.symbol.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_sorting() {
+        let mut no_margin = report("NONE", 15.0, 30.0);
+        no_margin.margin_of_safety = None;
+        let mut browser = ResultBrowser::new(vec![
+            report("B", 15.0, 30.0),
+            report("A", 30.0, 30.0),
+            no_margin,
+            report("C", 10.0, 30.0),
+        ]);
+        assert_eq!(symbols(&browser), vec!["A", "B", "C", "NONE"]);
+
+        browser.sort_by(SortColumn::Pe);
+        assert_eq!(symbols(&browser), vec!["C", "B", "NONE", "A"]);
+        browser.sort_by(SortColumn::Pe);
+        assert_eq!(symbols(&browser), vec!["A", "B", "NONE", "C"]);
+
+        browser.sort_by(SortColumn::MarginOfSafety);
+        assert_eq!(symbols(&browser).last(), Some(&"NONE"));
+    }
+
+    #[test]
+    fn test_filtering_and_selection() {
+        let mut browser = ResultBrowser::new(vec![
+            report("A", 15.0, 30.0),
+            report("B", 30.0, 30.0),
+            report("C", 10.0, 30.0),
+        ]);
+        browser.select_next();
+        browser.select_next();
+        assert_eq!(browser.selected_report().unwrap().stock_info.symbol, "C");
+
+        browser.cycle_total_filter();
+        assert_eq!(symbols(&browser), vec!["A", "C"]);
+        assert_eq!(browser.selected_report().unwrap().stock_info.symbol, "C");
+
+        let pe_index = browser
+            .criteria()
+            .iter()
+            .position(|name| name == "pe")
+            .unwrap();
+        browser.cycle_total_filter();
+        browser.cycle_total_filter();
+        browser.cycle_criterion_filter(pe_index);
+        browser.cycle_criterion_filter(pe_index);
+        assert_eq!(symbols(&browser), vec!["B"]);
+        assert_eq!(browser.selected_report().unwrap().stock_info.symbol, "B");
+
+        browser.cycle_total_filter();
+        assert!(browser.selected_report().is_none());
+        browser.select_next();
+        assert_eq!(browser.selected_index(), None);
+    }
+}
@@ -0,0 +1,762 @@
+use crate::financial_analysis::CriterionResult;
+use crate::stock_data_fetching::{CompanyFinancials, CompanyInformation, CompanyQuote};
+use anyhow::{Error, Result};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+
+// Names of the criteria the analysis evaluates itself, which rules may not take
+const BUILT_IN_CRITERIA: [&str; 15] = [
+    "market_cap",
+    "pe",
+    "dividends",
+    "earnings_growth",
+    "pb",
+    "pe_times_pb",
+    "debt_equity",
+    "working_capital",
+    "debt_working_capital",
+    "piotroski_f_score",
+    "altman_z_score",
+    "beneish_m_score",
+    "dcf_margin_of_safety",
+    "price_to_ncav",
+    "price_to_net_tangible_assets",
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Rule {
+    pub name: String, // Of the criterion in the report
+    pub expression: Expression,
+    // Whether failing the rule fails the stock, otherwise it only counts in the composite score
+    #[serde(default = "default_required")]
+    pub required: bool,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+// Each rule becomes a criterion of the report, found by its name
+pub fn validate_rules(rules: &[Rule]) -> Result<()> {
+    let mut names = HashSet::new();
+    for rule in rules {
+        if BUILT_IN_CRITERIA.contains(&rule.name.as_str()) {
+            return Err(Error::msg(format!(
+                "Rule name {} is taken by a built-in criterion",
+                rule.name
+            )));
+        }
+        if !names.insert(rule.name.as_str()) {
+            return Err(Error::msg(format!("Duplicate rule name {}", rule.name)));
+        }
+    }
+    Ok(())
+}
+
+// For the rules of the config, so that invalid names fail when it is loaded
+pub fn deserialize_rules<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Rule>, D::Error> {
+    let rules = Vec::<Rule>::deserialize(deserializer)?;
+    validate_rules(&rules).map_err(serde::de::Error::custom)?;
+    Ok(rules)
+}
+
+// A condition on the metrics of a company, e.g. "pe_ratio * pb_ratio <= 22.5" or
+// "current_ratio >= 2 or finnhub_industry == 'Utilities'". It is parsed and type checked when the
+// config is deserialized and serialized back to its source.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Expression {
+    source: String,
+    root: Node,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Type {
+    Number,
+    Text,
+    Boolean,
+}
+
+impl Type {
+    fn name(&self) -> &'static str {
+        match self {
+            Type::Number => "number",
+            Type::Text => "text",
+            Type::Boolean => "boolean",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Text(String),
+    Boolean(bool),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+impl Operator {
+    fn symbol(&self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Less => "<",
+            Operator::LessOrEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterOrEqual => ">=",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::And => "and",
+            Operator::Or => "or",
+        }
+    }
+}
+
+// Longest symbols first
+const COMPARISONS: [(&str, Operator); 6] = [
+    ("<=", Operator::LessOrEqual),
+    (">=", Operator::GreaterOrEqual),
+    ("==", Operator::Equal),
+    ("!=", Operator::NotEqual),
+    ("<", Operator::Less),
+    (">", Operator::Greater),
+];
+
+#[derive(Debug, Clone)]
+enum Node {
+    Number(f64),
+    Text(String),
+    Boolean(bool),
+    Metric(String),
+    Negate(Box<Node>),
+    Not(Box<Node>),
+    Binary(Operator, Box<Node>, Box<Node>),
+}
+
+// The data of the company a rule is evaluated on
+pub struct RuleContext<'a> {
+    pub financials: &'a CompanyFinancials,
+    pub information: &'a CompanyInformation,
+    pub quote: &'a CompanyQuote,
+}
+
+enum Metric {
+    Number(fn(&RuleContext) -> Option<f64>),
+    Text(fn(&RuleContext) -> String),
+}
+
+impl Metric {
+    fn value_type(&self) -> Type {
+        match self {
+            Metric::Number(_) => Type::Number,
+            Metric::Text(_) => Type::Text,
+        }
+    }
+}
+
+// The metrics rules can refer to, named like the fields they come from except the quote prices
+fn metric(name: &str) -> Option<Metric> {
+    let metric = match name {
+        // CompanyFinancials
+        "pb_ratio" => Metric::Number(|context| context.financials.pb_ratio),
+        "ps_ratio" => Metric::Number(|context| context.financials.ps_ratio),
+        "pe_ratio" => Metric::Number(|context| context.financials.pe_ratio),
+        "dividend_per_share" => Metric::Number(|context| context.financials.dividend_per_share),
+        "dividend_per_share_5_yr_avg" => {
+            Metric::Number(|context| context.financials.dividend_per_share_5_yr_avg)
+        }
+        "dividend_growth_5_yr_avg" => {
+            Metric::Number(|context| context.financials.dividend_growth_5_yr_avg)
+        }
+        "earnings_per_share" => Metric::Number(|context| context.financials.earnings_per_share),
+        "earnings_growth" => Metric::Number(|context| context.financials.earnings_growth),
+        "earnings_growth_5_yr_avg" => {
+            Metric::Number(|context| context.financials.earnings_growth_5_yr_avg)
+        }
+        "book_value_per_share" => Metric::Number(|context| context.financials.book_value_per_share),
+        "tangible_book_value_per_share" => {
+            Metric::Number(|context| context.financials.tangible_book_value_per_share)
+        }
+        "total_debt_to_total_equity" => {
+            Metric::Number(|context| context.financials.total_debt_to_total_equity)
+        }
+        "long_term_debt_to_equity" => {
+            Metric::Number(|context| context.financials.long_term_debt_to_equity)
+        }
+        "current_ratio" => Metric::Number(|context| context.financials.current_ratio),
+        "quick_ratio" => Metric::Number(|context| context.financials.quick_ratio),
+        "return_on_avg_equity" => Metric::Number(|context| context.financials.return_on_avg_equity),
+        "return_on_avg_equity_5_yr" => {
+            Metric::Number(|context| context.financials.return_on_avg_equity_5_yr)
+        }
+        "return_on_avg_assets_5_yr" => {
+            Metric::Number(|context| context.financials.return_on_avg_assets_5_yr)
+        }
+        "return_on_investments" => {
+            Metric::Number(|context| context.financials.return_on_investments)
+        }
+        "return_on_investments_5_yr" => {
+            Metric::Number(|context| context.financials.return_on_investments_5_yr)
+        }
+        "net_profit_margin" => Metric::Number(|context| context.financials.net_profit_margin),
+        "net_profit_margin_5_yr_avg" => {
+            Metric::Number(|context| context.financials.net_profit_margin_5_yr_avg)
+        }
+        "net_profit_margin_growth_5_yr_avg" => {
+            Metric::Number(|context| context.financials.net_profit_margin_growth_5_yr_avg)
+        }
+        "total_current_assets" => Metric::Number(|context| context.financials.total_current_assets),
+        "total_current_liabilities" => {
+            Metric::Number(|context| context.financials.total_current_liabilities)
+        }
+        "total_current_long_term_debt" => {
+            Metric::Number(|context| context.financials.total_current_long_term_debt)
+        }
+        "total_long_term_debt" => Metric::Number(|context| context.financials.total_long_term_debt),
+        // CompanyInformation
+        "market_cap" => Metric::Number(|context| Some(context.information.market_cap)),
+        "shares_outstanding" => {
+            Metric::Number(|context| Some(context.information.shares_outstanding))
+        }
+        "country" => Metric::Text(|context| context.information.country.clone()),
+        "currency" => Metric::Text(|context| context.information.currency.clone()),
+        "exchange" => Metric::Text(|context| context.information.exchange.clone()),
+        "industry" => Metric::Text(|context| context.information.industry.clone()),
+        "finnhub_industry" => Metric::Text(|context| context.information.finnhub_industry.clone()),
+        // CompanyQuote
+        "price" => Metric::Number(|context| Some(context.quote.c)),
+        "open" => Metric::Number(|context| Some(context.quote.o)),
+        "high" => Metric::Number(|context| Some(context.quote.h)),
+        "low" => Metric::Number(|context| Some(context.quote.l)),
+        "previous_close" => Metric::Number(|context| Some(context.quote.pc)),
+        _ => return None,
+    };
+    Some(metric)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Text(String),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < chars.len() {
+        let c = chars[position];
+        let start = position;
+        if c.is_whitespace() {
+            position += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            while position < chars.len()
+                && (chars[position].is_ascii_digit() || chars[position] == '.')
+            {
+                position += 1;
+            }
+            // Exponents like 20e3
+            if position < chars.len() && (chars[position] == 'e' || chars[position] == 'E') {
+                position += 1;
+                if position < chars.len() && (chars[position] == '-' || chars[position] == '+') {
+                    position += 1;
+                }
+                while position < chars.len() && chars[position].is_ascii_digit() {
+                    position += 1;
+                }
+            }
+            let number: String = chars[start..position].iter().collect();
+            let number = number
+                .parse()
+                .map_err(|_| Error::msg(format!("Invalid number {number}")))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            while position < chars.len()
+                && (chars[position].is_alphanumeric() || chars[position] == '_')
+            {
+                position += 1;
+            }
+            tokens.push(Token::Identifier(chars[start..position].iter().collect()));
+        } else if c == '\'' || c == '"' {
+            let end = chars[start + 1..]
+                .iter()
+                .position(|&other| other == c)
+                .ok_or_else(|| Error::msg("Unterminated text"))?;
+            position = start + 1 + end;
+            tokens.push(Token::Text(chars[start + 1..position].iter().collect()));
+            position += 1;
+        } else {
+            let rest: String = chars[start..].iter().take(2).collect();
+            let symbol = [
+                "<=", ">=", "==", "!=", "<", ">", "+", "-", "*", "/", "(", ")",
+            ]
+            .into_iter()
+            .find(|symbol| rest.starts_with(symbol))
+            .ok_or_else(|| Error::msg(format!("Unexpected character '{c}'")))?;
+            position += symbol.len();
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive descent from the lowest precedence: or, and, not, comparisons, + and -, * and /,
+// unary minus
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        let found = self.tokens.get(self.position) == Some(expected);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn eat_symbol(&mut self, symbol: &'static str) -> bool {
+        self.eat(&Token::Symbol(symbol))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        self.eat(&Token::Identifier(keyword.to_string()))
+    }
+
+    fn binary(operator: Operator, left: Node, right: Node) -> Node {
+        Node::Binary(operator, Box::new(left), Box::new(right))
+    }
+
+    fn parse_or(&mut self) -> Result<Node> {
+        let mut node = self.parse_and()?;
+        while self.eat_keyword("or") {
+            node = Self::binary(Operator::Or, node, self.parse_and()?);
+        }
+        Ok(node)
+    }
+
+    fn parse_and(&mut self) -> Result<Node> {
+        let mut node = self.parse_not()?;
+        while self.eat_keyword("and") {
+            node = Self::binary(Operator::And, node, self.parse_not()?);
+        }
+        Ok(node)
+    }
+
+    fn parse_not(&mut self) -> Result<Node> {
+        if self.eat_keyword("not") {
+            return Ok(Node::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Node> {
+        let left = self.parse_additive()?;
+        for (symbol, operator) in COMPARISONS {
+            if self.eat_symbol(symbol) {
+                return Ok(Self::binary(operator, left, self.parse_additive()?));
+            }
+        }
+        Ok(left)
+    }
+
+    fn parse_additive(&mut self) -> Result<Node> {
+        let mut node = self.parse_term()?;
+        loop {
+            let operator = if self.eat_symbol("+") {
+                Operator::Add
+            } else if self.eat_symbol("-") {
+                Operator::Subtract
+            } else {
+                return Ok(node);
+            };
+            node = Self::binary(operator, node, self.parse_term()?);
+        }
+    }
+
+    fn parse_term(&mut self) -> Result<Node> {
+        let mut node = self.parse_unary()?;
+        loop {
+            let operator = if self.eat_symbol("*") {
+                Operator::Multiply
+            } else if self.eat_symbol("/") {
+                Operator::Divide
+            } else {
+                return Ok(node);
+            };
+            node = Self::binary(operator, node, self.parse_unary()?);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Node> {
+        if self.eat_symbol("-") {
+            return Ok(Node::Negate(Box::new(self.parse_unary()?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Node> {
+        match self.next() {
+            Some(Token::Number(number)) => Ok(Node::Number(number)),
+            Some(Token::Text(text)) => Ok(Node::Text(text)),
+            Some(Token::Identifier(name)) => match name.as_str() {
+                "true" => Ok(Node::Boolean(true)),
+                "false" => Ok(Node::Boolean(false)),
+                "and" | "or" | "not" => Err(Error::msg(format!("Unexpected '{name}'"))),
+                _ => Ok(Node::Metric(name)),
+            },
+            Some(Token::Symbol("(")) => {
+                let node = self.parse_or()?;
+                if !self.eat_symbol(")") {
+                    return Err(Error::msg("Missing closing parenthesis"));
+                }
+                Ok(node)
+            }
+            Some(Token::Symbol(symbol)) => Err(Error::msg(format!("Unexpected '{symbol}'"))),
+            None => Err(Error::msg("Unexpected end of the expression")),
+        }
+    }
+}
+
+fn type_of(node: &Node) -> Result<Type> {
+    let expect = |operator: &str, operand: Type, expected: Type| {
+        if operand == expected {
+            Ok(())
+        } else {
+            Err(Error::msg(format!(
+                "'{operator}' expects a {}, not a {}",
+                expected.name(),
+                operand.name()
+            )))
+        }
+    };
+    match node {
+        Node::Number(_) => Ok(Type::Number),
+        Node::Text(_) => Ok(Type::Text),
+        Node::Boolean(_) => Ok(Type::Boolean),
+        Node::Metric(name) => metric(name)
+            .map(|metric| metric.value_type())
+            .ok_or_else(|| Error::msg(format!("Unknown metric {name}"))),
+        Node::Negate(operand) => {
+            expect("-", type_of(operand)?, Type::Number)?;
+            Ok(Type::Number)
+        }
+        Node::Not(operand) => {
+            expect("not", type_of(operand)?, Type::Boolean)?;
+            Ok(Type::Boolean)
+        }
+        Node::Binary(operator, left, right) => {
+            let (left, right) = (type_of(left)?, type_of(right)?);
+            let symbol = operator.symbol();
+            match operator {
+                Operator::Add | Operator::Subtract | Operator::Multiply | Operator::Divide => {
+                    expect(symbol, left, Type::Number)?;
+                    expect(symbol, right, Type::Number)?;
+                    Ok(Type::Number)
+                }
+                Operator::Less
+                | Operator::LessOrEqual
+                | Operator::Greater
+                | Operator::GreaterOrEqual => {
+                    expect(symbol, left, Type::Number)?;
+                    expect(symbol, right, Type::Number)?;
+                    Ok(Type::Boolean)
+                }
+                Operator::Equal | Operator::NotEqual => {
+                    expect(symbol, right, left)?;
+                    Ok(Type::Boolean)
+                }
+                Operator::And | Operator::Or => {
+                    expect(symbol, left, Type::Boolean)?;
+                    expect(symbol, right, Type::Boolean)?;
+                    Ok(Type::Boolean)
+                }
+            }
+        }
+    }
+}
+
+fn as_number(value: Value) -> Result<f64> {
+    match value {
+        Value::Number(number) => Ok(number),
+        _ => Err(Error::msg("Expected a number")),
+    }
+}
+
+fn as_boolean(value: Value) -> Result<bool> {
+    match value {
+        Value::Boolean(boolean) => Ok(boolean),
+        _ => Err(Error::msg("Expected a boolean")),
+    }
+}
+
+// A missing metric is an error, unless the other side of an and/or already decides the result
+fn evaluate(node: &Node, context: &RuleContext) -> Result<Value> {
+    match node {
+        Node::Number(number) => Ok(Value::Number(*number)),
+        Node::Text(text) => Ok(Value::Text(text.clone())),
+        Node::Boolean(boolean) => Ok(Value::Boolean(*boolean)),
+        Node::Metric(name) => match metric(name) {
+            Some(Metric::Number(value)) => value(context)
+                .map(Value::Number)
+                .ok_or_else(|| Error::msg(format!("No {name}"))),
+            Some(Metric::Text(value)) => Ok(Value::Text(value(context))),
+            None => Err(Error::msg(format!("Unknown metric {name}"))),
+        },
+        Node::Negate(operand) => Ok(Value::Number(-as_number(evaluate(operand, context)?)?)),
+        Node::Not(operand) => Ok(Value::Boolean(!as_boolean(evaluate(operand, context)?)?)),
+        Node::Binary(operator @ (Operator::And | Operator::Or), left, right) => {
+            // The value of either side that decides the result on its own
+            let decisive = *operator == Operator::Or;
+            let left = evaluate(left, context).and_then(as_boolean);
+            if left.as_ref().is_ok_and(|&left| left == decisive) {
+                return Ok(Value::Boolean(decisive));
+            }
+            let right = evaluate(right, context).and_then(as_boolean);
+            match (left, right) {
+                (_, Ok(right)) if right == decisive => Ok(Value::Boolean(decisive)),
+                (Err(error), _) | (_, Err(error)) => Err(error),
+                (Ok(_), Ok(_)) => Ok(Value::Boolean(!decisive)),
+            }
+        }
+        Node::Binary(operator @ (Operator::Equal | Operator::NotEqual), left, right) => {
+            let equal = evaluate(left, context)? == evaluate(right, context)?;
+            Ok(Value::Boolean(equal == (*operator == Operator::Equal)))
+        }
+        Node::Binary(operator, left, right) => {
+            let left = as_number(evaluate(left, context)?)?;
+            let right = as_number(evaluate(right, context)?)?;
+            Ok(match operator {
+                Operator::Add => Value::Number(left + right),
+                Operator::Subtract => Value::Number(left - right),
+                Operator::Multiply => Value::Number(left * right),
+                Operator::Divide if right == 0.0 => return Err(Error::msg("Division by zero")),
+                Operator::Divide => Value::Number(left / right),
+                Operator::Less => Value::Boolean(left < right),
+                Operator::LessOrEqual => Value::Boolean(left <= right),
+                Operator::Greater => Value::Boolean(left > right),
+                Operator::GreaterOrEqual => Value::Boolean(left >= right),
+                Operator::Equal | Operator::NotEqual | Operator::And | Operator::Or => {
+                    return Err(Error::msg("Expected an arithmetic or comparison operator"))
+                }
+            })
+        }
+    }
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self> {
+        let parse = || {
+            let mut parser = Parser {
+                tokens: tokenize(source)?,
+                position: 0,
+            };
+            let root = parser.parse_or()?;
+            if let Some(token) = parser.next() {
+                return Err(Error::msg(format!(
+                    "Unexpected {token:?} after the condition"
+                )));
+            }
+            let root_type = type_of(&root)?;
+            if root_type != Type::Boolean {
+                return Err(Error::msg(format!(
+                    "The expression is a {}, not a condition",
+                    root_type.name()
+                )));
+            }
+            Ok(root)
+        };
+        let root =
+            parse().map_err(|error| Error::msg(format!("Invalid rule \"{source}\": {error}")))?;
+        Ok(Self {
+            source: source.to_string(),
+            root,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn evaluate(&self, context: &RuleContext) -> Result<bool> {
+        as_boolean(evaluate(&self.root, context)?)
+    }
+}
+
+impl TryFrom<String> for Expression {
+    type Error = Error;
+
+    fn try_from(source: String) -> Result<Self> {
+        Expression::parse(&source)
+    }
+}
+
+impl From<Expression> for String {
+    fn from(expression: Expression) -> Self {
+        expression.source
+    }
+}
+
+// One criterion per rule, without a metric value or limits
+pub fn evaluate_rules(
+    rules: &[Rule],
+    financials: &CompanyFinancials,
+    information: &CompanyInformation,
+    quote: &CompanyQuote,
+) -> Vec<CriterionResult> {
+    let context = RuleContext {
+        financials,
+        information,
+        quote,
+    };
+    rules
+        .iter()
+        .map(|rule| {
+            let check = rule.expression.evaluate(&context);
+            if let Ok(passed) = check {
+                println!(
+                    "Rule {}: {} | Passed: {}",
+                    rule.name,
+                    rule.expression.source(),
+                    passed
+                );
+            }
+            CriterionResult::new(&rule.name, check, rule.required, None)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::financial_analysis::AnalysisConfig;
+    use crate::test_fixtures::{analysis_config, financials, information, quote};
+
+    fn check(source: &str, financials: &CompanyFinancials) -> Result<bool> {
+        let information = information("AAA");
+        let quote = quote(30.0);
+        Expression::parse(source)?.evaluate(&RuleContext {
+            financials,
+            information: &information,
+            quote: &quote,
+        })
+    }
+
+    #[test]
+    fn test_rule_evaluation() -> Result<()> {
+        // P/E 10 and P/B 1.2 from the fixtures
+        let mut financials = financials(10.0);
+        assert!(check("pe_ratio * pb_ratio <= 22.5", &financials)?);
+        assert!(!check("pe_ratio * pb_ratio <= 10", &financials)?);
+        assert!(check("-(pe_ratio - 12) / 2 == 1", &financials)?);
+        assert!(check("1 + 2 * 3 == 7 and not (price < 20e0)", &financials)?);
+        assert!(check(
+            "current_ratio >= 2 or industry == 'Utilities'",
+            &financials
+        )?);
+        assert!(check("country != \"NO\"", &financials)?);
+
+        // A missing metric fails the rule unless the other side decides it
+        financials.current_ratio = None;
+        assert!(check("current_ratio >= 2", &financials).is_err());
+        assert!(check(
+            "current_ratio >= 2 or industry == 'Retail'",
+            &financials
+        )?);
+        assert!(!check(
+            "industry == 'Utilities' and current_ratio >= 2",
+            &financials
+        )?);
+        assert!(check("current_ratio >= 2 or industry == 'Utilities'", &financials).is_err());
+        assert!(check("pe_ratio / 0 > 1", &financials).is_err());
+
+        let rules: Vec<Rule> = serde_json::from_value(serde_json::json!([
+            { "name": "graham_product", "expression": "pe_ratio * pb_ratio <= 22.5" },
+            { "name": "large", "expression": "market_cap >= 50e3", "required": false }
+        ]))?;
+        let criteria = evaluate_rules(&rules, &financials, &information("AAA"), &quote(30.0));
+        assert!(criteria[0].passed && criteria[0].required);
+        assert!(!criteria[1].passed && !criteria[1].required);
+        assert_eq!(
+            serde_json::to_value(&rules[0])?["expression"],
+            "pe_ratio * pb_ratio <= 22.5"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rule_type_checking() {
+        let error = |source: &str| Expression::parse(source).unwrap_err().to_string();
+        assert!(error("pe_ratio <= 'low'").contains("'<=' expects a number, not a text"));
+        assert!(error("country == 1").contains("'==' expects a text, not a number"));
+        assert!(error("pe_ratio and true").contains("'and' expects a boolean, not a number"));
+        assert!(error("pe_ratio * pb_ratio").contains("not a condition"));
+        assert!(error("pe <= 22.5").contains("Unknown metric pe"));
+        assert!(error("(pe_ratio <= 22.5").contains("Missing closing parenthesis"));
+        assert!(error("pe_ratio <= 22.5 pb_ratio").contains("after the condition"));
+        assert!(error("pe_ratio <= 22.5 and").contains("Unexpected end"));
+        assert!(error("country == 'US").contains("Unterminated text"));
+        assert!(error("pe_ratio % 2 == 0").contains("Unexpected character '%'"));
+
+        // Invalid rules fail when the config is loaded
+        assert!(serde_json::from_value::<Rule>(serde_json::json!({
+            "name": "typo",
+            "expression": "pe_ratoi < 15"
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn test_rule_names() {
+        let load = |rules: serde_json::Value| {
+            let mut config = serde_json::to_value(analysis_config()).unwrap();
+            config["rules"] = rules;
+            serde_json::from_value::<AnalysisConfig>(config).map_err(|error| error.to_string())
+        };
+        assert!(load(serde_json::json!([
+            { "name": "graham_product", "expression": "pe_ratio * pb_ratio <= 22.5" },
+            { "name": "liquidity", "expression": "current_ratio >= 2" }
+        ]))
+        .is_ok());
+
+        let error = load(serde_json::json!([
+            { "name": "liquidity", "expression": "current_ratio >= 2" },
+            { "name": "liquidity", "expression": "current_ratio >= 3" }
+        ]))
+        .unwrap_err();
+        assert!(error.contains("Duplicate rule name liquidity"));
+        let error = load(serde_json::json!([
+            { "name": "pe", "expression": "pe_ratio <= 15" }
+        ]))
+        .unwrap_err();
+        assert!(error.contains("Rule name pe is taken by a built-in criterion"));
+    }
+}
@@ -0,0 +1,251 @@
+use crate::financial_analysis::StockReport;
+use crate::storage::Storage;
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CriterionFlip {
+    pub criterion: String,
+    pub passed_before: bool,
+    pub passed_after: bool,
+    pub value_before: Option<f64>,
+    pub value_after: Option<f64>,
+    pub lower_limit: Option<f64>, // Limits of the later run
+    pub upper_limit: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StockChange {
+    pub symbol: String,
+    pub description: String,
+    pub flips: Vec<CriterionFlip>,
+    pub note: Option<String>, // Set when the stock is missing from one of the runs
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunDiff {
+    pub from_run: i64,
+    pub to_run: i64,
+    pub entered: Vec<StockChange>, // Newly qualifying stocks
+    pub left: Vec<StockChange>,    // Newly disqualified stocks
+}
+
+fn flipped_criteria(before: &StockReport, after: &StockReport) -> Vec<CriterionFlip> {
+    let mut flips = Vec::new();
+    for criterion_after in &after.criteria {
+        let criterion_before = match before.criterion(&criterion_after.name) {
+            Some(criterion) => criterion,
+            None => continue,
+        };
+        if criterion_before.passed == criterion_after.passed {
+            continue;
+        }
+        flips.push(CriterionFlip {
+            criterion: criterion_after.name.clone(),
+            passed_before: criterion_before.passed,
+            passed_after: criterion_after.passed,
+            value_before: criterion_before.value,
+            value_after: criterion_after.value,
+            lower_limit: criterion_after.lower_limit,
+            upper_limit: criterion_after.upper_limit,
+        });
+    }
+    flips
+}
+
+pub fn diff_reports(
+    from_run: i64,
+    from_reports: &[StockReport],
+    to_run: i64,
+    to_reports: &[StockReport],
+) -> RunDiff {
+    let before: BTreeMap<&str, &StockReport> = from_reports
+        .iter()
+        .map(|report| (report.stock_info.symbol.as_str(), report))
+        .collect();
+    let after: BTreeMap<&str, &StockReport> = to_reports
+        .iter()
+        .map(|report| (report.stock_info.symbol.as_str(), report))
+        .collect();
+
+    let mut entered = Vec::new();
+    let mut left = Vec::new();
+    for (symbol, report_after) in &after {
+        let report_before = before.get(symbol);
+        let passed_before = report_before.is_some_and(|report| report.passed);
+        if report_after.passed == passed_before {
+            continue;
+        }
+        let change = match report_before {
+            Some(report_before) => StockChange {
+                symbol: symbol.to_string(),
+                description: report_after.stock_info.description.clone(),
+                flips: flipped_criteria(report_before, report_after),
+                note: None,
+            },
+            None => StockChange {
+                symbol: symbol.to_string(),
+                description: report_after.stock_info.description.clone(),
+                flips: Vec::new(),
+                note: Some(format!("Not screened in run {from_run}")),
+            },
+        };
+        if report_after.passed {
+            entered.push(change);
+        } else {
+            left.push(change);
+        }
+    }
+
+    // Stocks that passed before but could not be screened in the later run
+    for (symbol, report_before) in &before {
+        if report_before.passed && !after.contains_key(symbol) {
+            left.push(StockChange {
+                symbol: symbol.to_string(),
+                description: report_before.stock_info.description.clone(),
+                flips: Vec::new(),
+                note: Some(format!("Not screened in run {to_run}")),
+            });
+        }
+    }
+    left.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+    RunDiff {
+        from_run,
+        to_run,
+        entered,
+        left,
+    }
+}
+
+pub fn diff_runs(storage: &Storage, from_run: i64, to_run: i64) -> Result<RunDiff> {
+    for run_id in [from_run, to_run] {
+        if storage.run(run_id)?.is_none() {
+            return Err(Error::msg(format!("No run with id {run_id}")));
+        }
+    }
+    let from_reports = storage.run_reports(from_run)?;
+    let to_reports = storage.run_reports(to_run)?;
+    Ok(diff_reports(from_run, &from_reports, to_run, &to_reports))
+}
+
+fn format_value(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |value| format!("{value:.2}"))
+}
+
+fn format_limits(lower_limit: Option<f64>, upper_limit: Option<f64>) -> String {
+    match (lower_limit, upper_limit) {
+        (Some(lower), Some(upper)) => format!("{lower:.2}..{upper:.2}"),
+        (Some(lower), None) => format!(">= {lower:.2}"),
+        (None, Some(upper)) => format!("<= {upper:.2}"),
+        (None, None) => "-".to_string(),
+    }
+}
+
+fn format_passed(passed: bool) -> &'static str {
+    if passed {
+        "pass"
+    } else {
+        "fail"
+    }
+}
+
+impl RunDiff {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn to_table(&self) -> String {
+        let mut table = format!("Run {} -> run {}\n", self.from_run, self.to_run);
+        for (title, changes) in [
+            ("Newly qualifying", &self.entered),
+            ("Newly disqualified", &self.left),
+        ] {
+            table += &format!("\n{} stocks: {}\n", title, changes.len());
+            if changes.is_empty() {
+                continue;
+            }
+            table += &format!(
+                "{:<8} {:<16} {:<6} {:<6} {:>12} {:>12}  {}\n",
+                "SYMBOL", "CRITERION", "BEFORE", "AFTER", "VALUE BEFORE", "VALUE AFTER", "LIMITS"
+            );
+            for change in changes {
+                if let Some(note) = &change.note {
+                    table += &format!("{:<8} {}\n", change.symbol, note);
+                }
+                for flip in &change.flips {
+                    table += &format!(
+                        "{:<8} {:<16} {:<6} {:<6} {:>12} {:>12}  {}\n",
+                        change.symbol,
+                        flip.criterion,
+                        format_passed(flip.passed_before),
+                        format_passed(flip.passed_after),
+                        format_value(flip.value_before),
+                        format_value(flip.value_after),
+                        format_limits(flip.lower_limit, flip.upper_limit),
+                    );
+                }
+            }
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{analysis_config, report};
+
+    #[test]
+    fn test_diff_reports() {
+        let before = vec![
+            report("KEEP", 10.0, 30.0),
+            report("DROP", 20.0, 30.0),
+            report("JOIN", 23.4, 30.0),
+            report("GONE", 10.0, 30.0),
+        ];
+        let after = vec![
+            report("KEEP", 11.0, 30.0),
+            report("DROP", 25.0, 30.0),
+            report("JOIN", 21.0, 30.0),
+            report("NEW", 10.0, 30.0),
+        ];
+
+        let diff = diff_reports(1, &before, 2, &after);
+        let entered: Vec<_> = diff.entered.iter().map(|c| c.symbol.as_str()).collect();
+        let left: Vec<_> = diff.left.iter().map(|c| c.symbol.as_str()).collect();
+        assert_eq!(entered, vec!["JOIN", "NEW"]);
+        assert_eq!(left, vec!["DROP", "GONE"]);
+
+        let join = &diff.entered[0];
+        assert_eq!(join.flips.len(), 1);
+        assert_eq!(join.flips[0].criterion, "pe");
+        assert!(!join.flips[0].passed_before && join.flips[0].passed_after);
+        assert_eq!(join.flips[0].value_before, Some(23.4));
+        assert_eq!(join.flips[0].upper_limit, Some(22.5));
+        assert!(diff.entered[1].note.is_some());
+
+        let table = diff.to_table();
+        assert!(table.contains("JOIN     pe"));
+        assert!(table.contains("2.00..22.50"));
+        let json: serde_json::Value = serde_json::from_str(&diff.to_json().unwrap()).unwrap();
+        assert_eq!(json["left"][0]["flips"][0]["value_after"], 25.0);
+    }
+
+    #[test]
+    fn test_diff_stored_runs() -> Result<()> {
+        let mut storage = Storage::open_in_memory()?;
+        for pe_ratio in [30.0, 15.0] {
+            let run_id = storage.begin_run("scan", &analysis_config())?;
+            storage.save_report(run_id, &report("AAPL", pe_ratio, 30.0))?;
+            storage.finish_run(run_id)?;
+        }
+
+        let diff = diff_runs(&storage, 1, 2)?;
+        assert_eq!(diff.entered.len(), 1);
+        assert!(diff.left.is_empty());
+        assert!(diff_runs(&storage, 1, 3).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use crate::financial_analysis::{StockAnalyzer, StockReport};
+use crate::scoring::{rank_reports, RankedStock};
+use crate::stock_data_fetching::StockInfo;
+use crate::storage::Storage;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScanResult {
+    pub run_id: i64,
+    pub worthy_stocks: Vec<StockInfo>,
+    pub shitty_stocks: Vec<StockInfo>, // Failing stocks and stocks whose data could not be fetched
+    pub ranked_stocks: Vec<RankedStock>, // The top stocks by the ranking key, passing or not
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Screen {
+    Defensive, // The criteria of evaluate_stock
+    NetNet,    // Stocks below a fraction of their net current asset value
+}
+
+impl Screen {
+    pub fn run_kind(&self) -> &'static str {
+        match self {
+            Screen::Defensive => "scan",
+            Screen::NetNet => "net_net_scan",
+        }
+    }
+}
+
+// Screens every stock on the exchange and stores the results as a "scan" run
+pub async fn scan_exchange(
+    analyzer: &mut StockAnalyzer,
+    storage: &mut Storage,
+    exchange: &str,
+) -> Result<ScanResult> {
+    scan_exchange_with(analyzer, storage, exchange, Screen::Defensive).await
+}
+
+// Stores the results as a run of the kind of the screen
+pub async fn scan_exchange_with(
+    analyzer: &mut StockAnalyzer,
+    storage: &mut Storage,
+    exchange: &str,
+    screen: Screen,
+) -> Result<ScanResult> {
+    let run_id = storage.begin_run(screen.run_kind(), &analyzer.analysis_config)?;
+
+    let stock_list = analyzer
+        .get_exchange_stock_list(&exchange.to_string())
+        .await?;
+    println!("Stocks on exchange {}: {}", exchange, stock_list.len());
+
+    let mut worthy_stocks = Vec::new();
+    let mut shitty_stocks = Vec::new();
+    let mut reports: Vec<StockReport> = Vec::new();
+
+    for stock in stock_list {
+        let report = match screen {
+            Screen::Defensive => analyzer.analyze_stock(&stock).await,
+            Screen::NetNet => analyzer.analyze_net_net(&stock).await,
+        };
+        match report {
+            Ok(report) => {
+                storage.save_report(run_id, &report)?;
+                if report.passed {
+                    worthy_stocks.push(report.stock_info.clone());
+                } else {
+                    shitty_stocks.push(report.stock_info.clone());
+                }
+                reports.push(report);
+            }
+            Err(e) => {
+                println!("Error checking stock {}: {}", stock.symbol, e);
+                storage.save_failure(run_id, &stock.symbol, &e.to_string())?;
+                shitty_stocks.push(stock);
+            }
+        }
+    }
+    storage.finish_run(run_id)?;
+
+    Ok(ScanResult {
+        run_id,
+        worthy_stocks,
+        shitty_stocks,
+        ranked_stocks: rank_reports(
+            &reports,
+            analyzer.analysis_config.top_n,
+            analyzer.analysis_config.ranking_key,
+        ),
+    })
+}
@@ -0,0 +1,219 @@
+use crate::financial_analysis::{CriterionResult, StockReport};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+// Relative distance of the metric from its nearest limit, clamped to [-1, 1]. Positive when the
+// criterion passed, so a stock narrowly missing a limit scores just below zero. A missing metric
+// scores -1 and a criterion without limits scores 1 or -1 from its verdict.
+pub fn criterion_score(criterion: &CriterionResult) -> f64 {
+    if criterion.error.is_some() {
+        return -1.0;
+    }
+    let verdict = if criterion.passed { 1.0 } else { -1.0 };
+    let value = match criterion.value {
+        Some(value) => value,
+        None => return verdict,
+    };
+    let scale = |limit: f64| if limit == 0.0 { 1.0 } else { limit.abs() };
+    let distances: Vec<f64> = [
+        criterion
+            .lower_limit
+            .map(|limit| (value - limit) / scale(limit)),
+        criterion
+            .upper_limit
+            .map(|limit| (limit - value) / scale(limit)),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    let distance = match distances.into_iter().reduce(f64::min) {
+        Some(distance) if distance.is_finite() => distance,
+        _ => return verdict,
+    };
+    // Some checks look at more than the reported metric, the verdict decides the sign
+    let score = if criterion.passed {
+        distance.max(0.0)
+    } else {
+        distance.min(0.0)
+    };
+    score.clamp(-1.0, 1.0)
+}
+
+// Weighted mean of the criterion scores, criteria without a weight count with weight 1. A
+// criterion that is not required does not count when its metric is missing, so that data the
+// verdict does not depend on cannot sink the score.
+pub fn composite_score(
+    criteria: &[CriterionResult],
+    weights: &HashMap<String, f64>,
+) -> Option<f64> {
+    let mut total = 0.0;
+    let mut total_weight = 0.0;
+    for criterion in criteria {
+        if criterion.error.is_some() && !criterion.required {
+            continue;
+        }
+        let weight = weights.get(&criterion.name).copied().unwrap_or(1.0);
+        total += weight * criterion_score(criterion);
+        total_weight += weight;
+    }
+    (total_weight > 0.0).then(|| total / total_weight)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum RankingKey {
+    #[default]
+    CompositeScore,
+    // Historical minus market-implied growth of the reverse DCF, the largest gap ranks first
+    GrowthGap,
+}
+
+impl RankingKey {
+    pub fn value(&self, report: &StockReport) -> Option<f64> {
+        match self {
+            RankingKey::CompositeScore => report.score,
+            RankingKey::GrowthGap => report
+                .implied_growth
+                .as_ref()
+                .and_then(|implied| implied.growth_gap),
+        }
+    }
+}
+
+impl fmt::Display for RankingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RankingKey::CompositeScore => write!(f, "composite score"),
+            RankingKey::GrowthGap => write!(f, "growth gap"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RankedStock {
+    pub rank: usize, // Starting at 1
+    pub symbol: String,
+    pub score: f64,   // The value of the ranking key
+    pub passed: bool, // The strict verdict of the required criteria
+}
+
+// The top_n stocks by the ranking key, highest first, stocks without a value are left out
+pub fn rank_reports<'a>(
+    reports: impl IntoIterator<Item = &'a StockReport>,
+    top_n: usize,
+    ranking_key: RankingKey,
+) -> Vec<RankedStock> {
+    let mut scored: Vec<(&StockReport, f64)> = reports
+        .into_iter()
+        .filter_map(|report| ranking_key.value(report).map(|score| (report, score)))
+        .collect();
+    scored.sort_by(|(a, a_score), (b, b_score)| {
+        b_score
+            .partial_cmp(a_score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.stock_info.symbol.cmp(&b.stock_info.symbol))
+    });
+    scored
+        .into_iter()
+        .take(top_n)
+        .enumerate()
+        .map(|(index, (report, score))| RankedStock {
+            rank: index + 1,
+            symbol: report.stock_info.symbol.clone(),
+            score,
+            passed: report.passed,
+        })
+        .collect()
+}
+
+pub fn ranking_table(ranked_stocks: &[RankedStock]) -> String {
+    let mut table = format!(
+        "{:<6}{:<10}{:>8}  {}\n",
+        "RANK", "SYMBOL", "SCORE", "PASSED"
+    );
+    for stock in ranked_stocks {
+        table += &format!(
+            "{:<6}{:<10}{:>8.3}  {}\n",
+            stock.rank, stock.symbol, stock.score, stock.passed
+        );
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::report;
+
+    #[test]
+    fn test_criterion_score() {
+        let criterion = |passed, value| {
+            CriterionResult::new("pe", Ok(passed), true, Some(value))
+                .with_limits(Some(2.0), Some(22.5))
+        };
+        // 10% from the upper limit
+        assert!((criterion_score(&criterion(true, 20.25)) - 0.1).abs() < 1e-9);
+        // Narrowly missing scores just below zero instead of like junk
+        assert!((criterion_score(&criterion(false, 23.625)) + 0.05).abs() < 1e-9);
+        assert_eq!(criterion_score(&criterion(false, 100.0)), -1.0);
+
+        let missing = CriterionResult::new("pe", Err(anyhow::Error::msg("No P/E")), true, None);
+        assert_eq!(criterion_score(&missing), -1.0);
+        let without_limits = CriterionResult::new("custom", Ok(true), false, None);
+        assert_eq!(criterion_score(&without_limits), 1.0);
+    }
+
+    #[test]
+    fn test_ranking() {
+        let cheap = report("CHEAP", 5.0, 30.0);
+        let fair = report("FAIR", 20.0, 30.0);
+        let expensive = report("EXPENSIVE", 23.0, 30.0);
+        let mut unscored = report("NONE", 10.0, 30.0);
+        unscored.score = None;
+        assert!(!expensive.passed);
+
+        let ranked = rank_reports(
+            [&fair, &expensive, &unscored, &cheap],
+            2,
+            RankingKey::CompositeScore,
+        );
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].symbol, "CHEAP");
+        assert_eq!(ranked[0].rank, 1);
+        assert_eq!(ranked[1].symbol, "FAIR");
+        assert_eq!(RankingKey::GrowthGap.to_string(), "growth gap");
+
+        // Weighting P/E heavily still ranks the near miss above a stock failing it badly
+        let mut weights = HashMap::new();
+        weights.insert("pe".to_string(), 10.0);
+        let far_miss = report("FAR", 60.0, 30.0);
+        assert!(
+            composite_score(&expensive.criteria, &weights)
+                > composite_score(&far_miss.criteria, &weights)
+        );
+        assert_eq!(composite_score(&[], &weights), None);
+    }
+
+    #[test]
+    fn test_composite_score_with_missing_metrics() {
+        let passing = CriterionResult::new("pe", Ok(true), true, Some(20.25))
+            .with_limits(Some(2.0), Some(22.5));
+        let missing = |required| {
+            CriterionResult::new(
+                "ddm",
+                Err(anyhow::Error::msg("No dividends")),
+                required,
+                None,
+            )
+        };
+        let weights = HashMap::new();
+        let score = composite_score(&[passing.clone(), missing(false)], &weights).unwrap();
+        assert!((score - 0.1).abs() < 1e-9);
+        // A required criterion without its metric still scores -1
+        let score = composite_score(&[passing, missing(true)], &weights).unwrap();
+        assert!((score + 0.45).abs() < 1e-9);
+        assert_eq!(composite_score(&[missing(false)], &weights), None);
+    }
+}
@@ -0,0 +1,441 @@
+use crate::financial_analysis::{evaluate_stock, AnalysisConfig, StockAnalyzer, StockReport};
+use crate::scoring::{rank_reports, RankedStock};
+use crate::stock_data_fetching::{NoDataError, StockInfo};
+use crate::storage::{RunSummary, Storage, WatchlistEntry};
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tokio::time::{Duration, Instant};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ServerConfig {
+    #[serde(default = "default_bind_address")]
+    pub bind_address: String,
+    // Budget of symbols fetched from Finnhub on behalf of clients, shared by all clients
+    #[serde(default = "default_symbols_per_minute")]
+    pub symbols_per_minute: u32,
+    #[serde(default = "default_max_screen_symbols")]
+    pub max_screen_symbols: usize,
+}
+
+fn default_bind_address() -> String {
+    "127.0.0.1:3000".to_string()
+}
+
+fn default_symbols_per_minute() -> u32 {
+    5
+}
+
+fn default_max_screen_symbols() -> usize {
+    5
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind_address: default_bind_address(),
+            symbols_per_minute: default_symbols_per_minute(),
+            max_screen_symbols: default_max_screen_symbols(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn from_json_value(json: serde_json::Value) -> Self {
+        if json.is_null() {
+            return Self::default();
+        }
+        serde_json::from_value(json).unwrap()
+    }
+}
+
+// Fixed window limiter on the number of symbols fetched per minute. StockAnalyzer already keeps
+// within the Finnhub limit by sleeping, this keeps clients from queueing up more work than that.
+pub struct RateLimiter {
+    capacity: u32,
+    window: Duration,
+    window_start: Option<Instant>, // Set by the first request
+    used: u32,
+}
+
+impl RateLimiter {
+    pub fn new(capacity: u32, window: Duration) -> Self {
+        Self {
+            capacity,
+            window,
+            window_start: None,
+            used: 0,
+        }
+    }
+
+    // Returns how long to wait when the cost does not fit in the current window
+    pub fn try_acquire(&mut self, cost: u32, now: Instant) -> Result<(), Duration> {
+        let window_start = match self.window_start {
+            Some(window_start) if now.duration_since(window_start) < self.window => window_start,
+            _ => {
+                self.window_start = Some(now);
+                self.used = 0;
+                now
+            }
+        };
+        if self.used + cost > self.capacity {
+            return Err(self.window - now.duration_since(window_start));
+        }
+        self.used += cost;
+        Ok(())
+    }
+}
+
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+    retry_after: Option<Duration>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: &str) -> Self {
+        Self {
+            status,
+            message: message.to_string(),
+            retry_after: None,
+        }
+    }
+}
+
+// Errors that are not handled explicitly come from fetching or storing data
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        if error.is::<NoDataError>() {
+            return Self::new(StatusCode::NOT_FOUND, &error.to_string());
+        }
+        Self::new(StatusCode::BAD_GATEWAY, &error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({ "error": self.message }));
+        match self.retry_after {
+            Some(retry_after) => {
+                let retry_after = retry_after.as_secs().max(1).to_string();
+                (self.status, [(header::RETRY_AFTER, retry_after)], body).into_response()
+            }
+            None => (self.status, body).into_response(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    pub analyzer: Arc<Mutex<StockAnalyzer>>,
+    pub storage: Arc<Mutex<Storage>>,
+    pub rate_limiter: Arc<Mutex<RateLimiter>>,
+    pub max_screen_symbols: usize,
+}
+
+impl AppState {
+    pub fn new(analyzer: StockAnalyzer, storage: Storage, server_config: &ServerConfig) -> Self {
+        Self {
+            analyzer: Arc::new(Mutex::new(analyzer)),
+            storage: Arc::new(Mutex::new(storage)),
+            rate_limiter: Arc::new(Mutex::new(RateLimiter::new(
+                server_config.symbols_per_minute,
+                Duration::from_secs(60),
+            ))),
+            // A larger screen would never fit in the budget of a minute, so it is rejected up
+            // front instead of being told to retry
+            max_screen_symbols: server_config
+                .max_screen_symbols
+                .min(server_config.symbols_per_minute as usize),
+        }
+    }
+
+    async fn acquire(&self, num_symbols: usize) -> Result<(), ApiError> {
+        let mut rate_limiter = self.rate_limiter.lock().await;
+        rate_limiter
+            .try_acquire(num_symbols as u32, Instant::now())
+            .map_err(|retry_after| ApiError {
+                status: StatusCode::TOO_MANY_REQUESTS,
+                message: "Finnhub request budget exhausted, try again later".to_string(),
+                retry_after: Some(retry_after),
+            })
+    }
+}
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/stocks/:symbol/report", get(stock_report))
+        .route("/screen", post(screen))
+        .route("/runs/:id", get(run))
+        .route("/watchlist", get(watchlist))
+        .with_state(state)
+}
+
+async fn stock_report(
+    State(state): State<AppState>,
+    Path(symbol): Path<String>,
+) -> Result<Json<StockReport>, ApiError> {
+    // Stored reports are served without spending the request budget
+    let stock_info = StockInfo::from_symbol(&symbol.to_uppercase());
+    if let Some(report) = state
+        .storage
+        .lock()
+        .await
+        .latest_report(&stock_info.symbol)?
+    {
+        return Ok(Json(report));
+    }
+    state.acquire(1).await?;
+    let report = state
+        .analyzer
+        .lock()
+        .await
+        .analyze_stock(&stock_info)
+        .await?;
+    Ok(Json(report))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScreenRequest {
+    pub symbols: Vec<String>,
+    pub analysis_config: AnalysisConfig,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScreenError {
+    pub symbol: String,
+    pub error: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScreenResponse {
+    pub reports: Vec<StockReport>,
+    pub errors: Vec<ScreenError>, // Symbols whose data could not be fetched
+    pub ranked_stocks: Vec<RankedStock>,
+}
+
+async fn screen(
+    State(state): State<AppState>,
+    Json(request): Json<ScreenRequest>,
+) -> Result<Json<ScreenResponse>, ApiError> {
+    if request.symbols.len() > state.max_screen_symbols {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            &format!(
+                "At most {} symbols can be screened per request",
+                state.max_screen_symbols
+            ),
+        ));
+    }
+    state.acquire(request.symbols.len()).await?;
+
+    let mut response = ScreenResponse {
+        reports: Vec::new(),
+        errors: Vec::new(),
+        ranked_stocks: Vec::new(),
+    };
+    let mut analyzer = state.analyzer.lock().await;
+    for symbol in &request.symbols {
+        let stock_info = StockInfo::from_symbol(&symbol.to_uppercase());
+        match analyzer.get_stock_data(&stock_info).await {
+            Ok((financials, information, quote)) => response.reports.push(evaluate_stock(
+                &stock_info,
+                financials,
+                information,
+                quote,
+                &request.analysis_config,
+            )),
+            Err(error) => response.errors.push(ScreenError {
+                symbol: stock_info.symbol,
+                error: error.to_string(),
+            }),
+        }
+    }
+    response.ranked_stocks = rank_reports(
+        &response.reports,
+        request.analysis_config.top_n,
+        request.analysis_config.ranking_key,
+    );
+    Ok(Json(response))
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RunResponse {
+    pub run: RunSummary,
+    pub reports: Vec<StockReport>,
+}
+
+async fn run(
+    State(state): State<AppState>,
+    Path(run_id): Path<i64>,
+) -> Result<Json<RunResponse>, ApiError> {
+    let storage = state.storage.lock().await;
+    let run = storage
+        .run(run_id)?
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, &format!("No run with id {run_id}")))?;
+    let reports = storage.run_reports(run_id)?;
+    Ok(Json(RunResponse { run, reports }))
+}
+
+async fn watchlist(State(state): State<AppState>) -> Result<Json<Vec<WatchlistEntry>>, ApiError> {
+    Ok(Json(state.storage.lock().await.watchlist()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{analysis_config, report};
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    fn test_state(symbols_per_minute: u32) -> anyhow::Result<AppState> {
+        let mut storage = Storage::open_in_memory()?;
+        let run_id = storage.begin_run("scan", &analysis_config())?;
+        storage.save_report(run_id, &report("AAPL", 12.0, 30.0))?;
+        storage.finish_run(run_id)?;
+        storage.add_to_watchlist(&WatchlistEntry::new("MSFT", "Cloud", Some(200.0)))?;
+
+        let server_config = ServerConfig {
+            symbols_per_minute,
+            ..ServerConfig::default()
+        };
+        Ok(AppState::new(
+            StockAnalyzer::new("config/example.json"),
+            storage,
+            &server_config,
+        ))
+    }
+
+    async fn get_json(app: Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let response = app
+            .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let start = Instant::now();
+        let mut rate_limiter = RateLimiter::new(5, Duration::from_secs(60));
+        assert!(rate_limiter.try_acquire(3, start).is_ok());
+        assert!(rate_limiter.try_acquire(2, start).is_ok());
+        let retry_after = rate_limiter
+            .try_acquire(1, start + Duration::from_secs(20))
+            .unwrap_err();
+        assert_eq!(retry_after, Duration::from_secs(40));
+        assert!(rate_limiter
+            .try_acquire(5, start + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_stored_results() -> anyhow::Result<()> {
+        let app = router(test_state(5)?);
+
+        let (status, body) = get_json(app.clone(), "/runs/1").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["run"]["num_passed"], 1);
+        assert_eq!(body["reports"][0]["stock_info"]["symbol"], "AAPL");
+
+        // Served from storage, so even without a request budget
+        let app = router(test_state(0)?);
+        let (status, body) = get_json(app.clone(), "/stocks/aapl/report").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["stock_info"]["symbol"], "AAPL");
+
+        let (status, body) = get_json(app.clone(), "/runs/2").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "No run with id 2");
+
+        let (status, body) = get_json(app, "/watchlist").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["symbol"], "MSFT");
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_status() {
+        let error = anyhow::Error::from(NoDataError {
+            symbol: "XXXX".to_string(),
+        });
+        let error = ApiError::from(error);
+        assert_eq!(error.status, StatusCode::NOT_FOUND);
+        assert_eq!(error.message, "No data for symbol XXXX");
+
+        let error = ApiError::from(anyhow::Error::msg("Connection reset"));
+        assert_eq!(error.status, StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_requests() -> anyhow::Result<()> {
+        let app = router(test_state(0)?);
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/stocks/TSLA/report").body(Body::empty())?)
+            .await?;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+
+        let request = ScreenRequest {
+            symbols: (0..30).map(|i| format!("S{i}")).collect(),
+            analysis_config: analysis_config(),
+        };
+        let response = app
+            .oneshot(
+                Request::post("/screen")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(serde_json::to_vec(&request)?))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_screen_larger_than_budget() -> anyhow::Result<()> {
+        let app = router(test_state(default_symbols_per_minute())?);
+
+        let request = ScreenRequest {
+            symbols: (0..6).map(|i| format!("S{i}")).collect(),
+            analysis_config: analysis_config(),
+        };
+        let response = app
+            .oneshot(
+                Request::post("/screen")
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(serde_json::to_vec(&request)?))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(!response.headers().contains_key(header::RETRY_AFTER));
+        let body = hyper::body::to_bytes(response.into_body()).await?;
+        let body: serde_json::Value = serde_json::from_slice(&body)?;
+        assert_eq!(
+            body["error"],
+            "At most 5 symbols can be screened per request"
+        );
+
+        // Also when configured to allow more symbols per screen than per minute
+        let server_config = ServerConfig {
+            max_screen_symbols: 20,
+            ..ServerConfig::default()
+        };
+        let state = AppState::new(
+            StockAnalyzer::new("config/example.json"),
+            Storage::open_in_memory()?,
+            &server_config,
+        );
+        assert_eq!(state.max_screen_symbols, 5);
+        Ok(())
+    }
+}
@@ -0,0 +1,11 @@
+use anyhow::Result;
+use config::Config;
+
+// Reads the whole settings file, each module deserializes its own section from it
+pub fn load_settings(settings_filename: &str) -> Result<serde_json::Value> {
+    let settings = Config::builder()
+        .add_source(config::File::with_name(settings_filename))
+        .build()?
+        .try_deserialize::<serde_json::Value>()?;
+    Ok(settings)
+}
@@ -1,13 +1,38 @@
+use crate::fundamentals_history::{FundamentalsHistory, ReportedStatements};
+use crate::universe_filter::UniverseFilterConfig;
 use anyhow::{Error, Result};
 use config::Config;
 use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct DataFetchConfig {
     pub finnhub_api_key: String,
     pub max_api_calls_per_minute: u32,
     pub considered_exchanges: Vec<String>,
+    #[serde(default = "default_finnhub_websocket_url")]
+    pub finnhub_websocket_url: String,
+    #[serde(default)]
+    pub universe_filter: UniverseFilterConfig,
+}
+
+// Finnhub answers requests for unknown symbols with empty data instead of an error status
+#[derive(Debug)]
+pub struct NoDataError {
+    pub symbol: String,
+}
+
+impl fmt::Display for NoDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "No data for symbol {}", self.symbol)
+    }
+}
+
+impl std::error::Error for NoDataError {}
+
+fn default_finnhub_websocket_url() -> String {
+    "wss://ws.finnhub.io".to_string()
 }
 
 impl DataFetchConfig {
@@ -38,6 +63,29 @@ pub struct StockInfo {
     pub symbol: String,
     pub currency: String,
     pub description: String,
+    #[serde(default)]
+    pub display_symbol: String,
+    #[serde(default)]
+    pub figi: String,
+    #[serde(default)]
+    pub mic: String, // Market identifier code of the primary listing
+    #[serde(default)]
+    pub security_type: String, // E.g. "Common Stock", "ADR", "REIT" or "Preference"
+}
+
+impl StockInfo {
+    // For symbols that do not come from an exchange listing, e.g. the watchlist
+    pub fn from_symbol(symbol: &str) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            currency: String::new(),
+            description: String::new(),
+            display_symbol: symbol.to_string(),
+            figi: String::new(),
+            mic: String::new(),
+            security_type: String::new(),
+        }
+    }
 }
 
 pub async fn extract_stock_list_from_exchange(
@@ -45,7 +93,6 @@ pub async fn extract_stock_list_from_exchange(
     exchange: &String,
     api_key: &String,
 ) -> Result<Vec<StockInfo>, Error> {
-    let mut stocks: Vec<StockInfo> = Vec::new();
     let url = format!(
         "https://finnhub.io/api/v1/stock/symbol?exchange={exchange}&token={api_key}"
     );
@@ -57,19 +104,30 @@ pub async fn extract_stock_list_from_exchange(
         .json::<serde_json::Value>()
         .await?;
 
-    // println!("{:?}", response.as_array().unwrap());
-    for stock_info_val in response.as_array().unwrap() {
-        // println!("{}", stock_info_val.as_str().unwrap());
+    parse_stock_list(&response)
+}
 
-        if stock_info_val["type"].as_str().unwrap() != "Common Stock" {
-            continue;
-        }
+// Keeps every listing, the universe is narrowed down afterwards by the configured UniverseFilter
+pub fn parse_stock_list(response: &serde_json::Value) -> Result<Vec<StockInfo>, Error> {
+    let stock_info_vals = response
+        .as_array()
+        .ok_or_else(|| Error::msg("Stock list is not an array"))?;
 
+    let mut stocks: Vec<StockInfo> = Vec::new();
+    for stock_info_val in stock_info_vals {
+        let field = |name: &str| stock_info_val[name].as_str().unwrap_or("").to_string();
         let stock_info = StockInfo {
-            symbol: stock_info_val["symbol"].as_str().unwrap().to_string(),
-            currency: stock_info_val["currency"].as_str().unwrap().to_string(),
-            description: stock_info_val["description"].as_str().unwrap().to_string(),
+            symbol: field("symbol"),
+            currency: field("currency"),
+            description: field("description"),
+            display_symbol: field("displaySymbol"),
+            figi: field("figi"),
+            mic: field("mic"),
+            security_type: field("type"),
         };
+        if stock_info.symbol.is_empty() {
+            continue;
+        }
         stocks.push(stock_info);
     }
 
@@ -101,6 +159,36 @@ impl CompanyQuote {
     }
 }
 
+// Daily closing prices, Finnhub leaves out the arrays when there is no data in the range
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StockCandles {
+    #[serde(default)]
+    pub c: Vec<f64>, // close prices
+    #[serde(default)]
+    pub t: Vec<i64>, // timestamps
+    pub s: String,   // status, "ok" or "no_data"
+}
+
+impl StockCandles {
+    pub async fn get(
+        client: &reqwest::Client,
+        symbol: &String,
+        from: i64,
+        to: i64,
+        api_key: &String,
+    ) -> Result<Self, Error> {
+        let url = format!(
+            "https://finnhub.io/api/v1/stock/candle?symbol={symbol}&resolution=D&from={from}&to={to}&token={api_key}"
+        );
+        let url = Url::parse(&url)?;
+        let res = client.get(url).send().await?.json::<StockCandles>().await?;
+        if res.s != "ok" && res.s != "no_data" {
+            return Err(Error::msg(format!("Unexpected candle status {}", res.s)));
+        }
+        Ok(res)
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CompanyInformation {
     pub name: String,
@@ -134,6 +222,9 @@ impl CompanyInformation {
             .await?;
 
         //println!("{:?}", response);
+        if response["name"].is_null() {
+            return Err(NoDataError { symbol: symbol.clone() }.into());
+        }
         let res = CompanyInformation {
             name: response["name"].as_str().unwrap().to_string(),
             ticker: response["ticker"].as_str().unwrap().to_string(),
@@ -153,7 +244,7 @@ impl CompanyInformation {
 }
 
 // The financials are annual unless otherwise specified
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct CompanyFinancials {
     pub pb_ratio: Option<f64>,
     pub ps_ratio: Option<f64>,
@@ -181,8 +272,15 @@ pub struct CompanyFinancials {
     pub total_current_assets: Option<f64>,
     pub total_current_liabilities: Option<f64>,
     pub total_current_long_term_debt: Option<f64>,
+    #[serde(default)]
+    pub total_long_term_debt: Option<f64>, // Including the current portion
+    #[serde(default)]
+    pub reported_statements: Vec<ReportedStatements>, // Latest annual periods, newest first
 }
 
+// Enough annual periods for the year over year changes of the financial strength scores
+pub const NUM_REPORTED_PERIODS: usize = 3;
+
 impl CompanyFinancials {
     pub async fn get(
         client: &reqwest::Client,
@@ -200,6 +298,12 @@ impl CompanyFinancials {
             .await?
             .json::<serde_json::Value>()
             .await?;
+        if financial_response["metric"]
+            .as_object()
+            .is_none_or(|metric| metric.is_empty())
+        {
+            return Err(NoDataError { symbol: symbol.clone() }.into());
+        }
 
         let url = format!(
             "https://finnhub.io/api/v1/stock/financials-reported?symbol={symbol}&token={api_key}&freq=annual"
@@ -219,29 +323,13 @@ impl CompanyFinancials {
         financial_response: serde_json::Value,
         bs_response: serde_json::Value,
     ) -> Result<Self, Error> {
-        if bs_response["data"][0]["report"]["bs"].is_null() {
-            return Err(Error::msg("Null value for company balance sheet."));
-        }
-        let mut total_current_assets = Some(0.0_f64);
-        let mut total_current_liabilities = Some(0.0_f64);
-        let mut total_current_long_term_debt = Some(0.0_f64);
+        // The latest reported period, Finnhub does not guarantee that it comes first
+        let history = FundamentalsHistory::from_serde_json_value(&bs_response)?;
+        let balance_sheet = history
+            .latest()
+            .map(|filing| filing.balance_sheet)
+            .ok_or_else(|| Error::msg("Null value for company balance sheet."))?;
         //println!("Financials: {:?}", financial_response["metric"]);
-        for entry in bs_response["data"][0]["report"]["bs"].as_array().unwrap() {
-            //println!("Entry: {:?}", entry);
-            let label = entry["label"].as_str().unwrap();
-            if label == "Total current assets" {
-                total_current_assets = Some(entry["value"].as_f64().unwrap() / 1e6_f64);
-            }
-
-            if label == "Total current liabilities" {
-                total_current_liabilities = Some(entry["value"].as_f64().unwrap() / 1e6_f64);
-            }
-
-            let concept = entry["concept"].as_str().unwrap();
-            if label == "Term debt" && concept == "us-gaap_LongTermDebtCurrent" {
-                total_current_long_term_debt = Some(entry["value"].as_f64().unwrap() / 1e6_f64);
-            }
-        }
 
         let mut res = CompanyFinancials {
             pb_ratio: financial_response["metric"]["pbAnnual"].as_f64(),
@@ -273,9 +361,14 @@ impl CompanyFinancials {
             net_profit_margin_5_yr_avg: financial_response["metric"]["netProfitMargin5Y"].as_f64(),
             net_profit_margin_growth_5_yr_avg: financial_response["metric"]["netMarginGrowth5Y"]
                 .as_f64(),
-            total_current_assets,
-            total_current_liabilities,
-            total_current_long_term_debt,
+            // Current items missing from the report count as zero
+            total_current_assets: Some(balance_sheet.total_current_assets.unwrap_or(0.0)),
+            total_current_liabilities: Some(balance_sheet.total_current_liabilities.unwrap_or(0.0)),
+            total_current_long_term_debt: Some(
+                balance_sheet.total_current_long_term_debt.unwrap_or(0.0),
+            ),
+            total_long_term_debt: balance_sheet.total_long_term_debt,
+            reported_statements: history.recent_statements(NUM_REPORTED_PERIODS),
         };
 
         res.total_debt_to_total_equity = res.total_debt_to_total_equity.map(|x| x / 100.0);
@@ -317,6 +410,40 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_stock_list() -> Result<()> {
+        let response = serde_json::json!([
+            {
+                "currency": "USD",
+                "description": "APPLE INC",
+                "displaySymbol": "AAPL",
+                "figi": "BBG000B9XRY4",
+                "mic": "XNAS",
+                "symbol": "AAPL",
+                "type": "Common Stock"
+            },
+            {
+                "currency": "USD",
+                "description": "REALTY INCOME CORP",
+                "displaySymbol": "O",
+                "figi": null,
+                "mic": "XNYS",
+                "symbol": "O",
+                "type": "REIT"
+            }
+        ]);
+
+        let stocks = parse_stock_list(&response)?;
+        assert_eq!(stocks.len(), 2);
+        assert_eq!(stocks[0].mic, "XNAS");
+        assert_eq!(stocks[0].figi, "BBG000B9XRY4");
+        assert_eq!(stocks[1].security_type, "REIT");
+        assert_eq!(stocks[1].figi, "");
+
+        assert!(parse_stock_list(&serde_json::json!({"error": "Invalid API key"})).is_err());
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_company_quote() -> Result<()> {
         let config_json_value = Config::builder()
@@ -0,0 +1,583 @@
+use crate::financial_analysis::{AnalysisConfig, StockReport};
+use crate::stock_data_fetching::{CompanyFinancials, CompanyInformation, CompanyQuote};
+use anyhow::{Error, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct StorageConfig {
+    pub database_path: String,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            database_path: "financial_analysis.db".to_string(),
+        }
+    }
+}
+
+impl StorageConfig {
+    pub fn from_json_value(json: serde_json::Value) -> Self {
+        if json.is_null() {
+            return Self::default();
+        }
+        serde_json::from_value(json).unwrap()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RunSummary {
+    pub id: i64,
+    pub kind: String,
+    pub started_at: i64,          // Unix timestamp
+    pub finished_at: Option<i64>, // Unix timestamp, None while running
+    pub num_stocks: u32,
+    pub num_passed: u32,
+    pub num_failures: u32, // Stocks whose data could not be fetched
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Snapshot {
+    pub run_id: i64,
+    pub symbol: String,
+    pub fetched_at: i64, // Unix timestamp
+    pub financials: CompanyFinancials,
+    pub information: CompanyInformation,
+    pub quote: CompanyQuote,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MetricPoint {
+    pub run_id: i64,
+    pub fetched_at: i64, // Unix timestamp
+    pub value: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchlistEntry {
+    pub symbol: String,
+    pub note: String,
+    pub target_price: Option<f64>, // Price at which we would like to buy
+    pub added_at: i64,             // Unix timestamp
+}
+
+impl WatchlistEntry {
+    pub fn new(symbol: &str, note: &str, target_price: Option<f64>) -> Self {
+        Self {
+            symbol: symbol.to_uppercase(),
+            note: note.to_string(),
+            target_price,
+            added_at: unix_timestamp(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JobState {
+    pub name: String,
+    pub last_run_at: i64, // Unix timestamp of when the last run finished
+    pub last_run_id: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+// Runs that re-evaluate stored fundamentals with fresh quotes. Their snapshots repeat fundamentals
+// fetched earlier, so they are left out of the snapshot queries.
+pub const QUOTES_RUN_KIND: &str = "quotes";
+
+pub fn unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    kind TEXT NOT NULL,
+    started_at INTEGER NOT NULL,
+    finished_at INTEGER,
+    analysis_config TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS snapshots (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    symbol TEXT NOT NULL,
+    fetched_at INTEGER NOT NULL,
+    financials TEXT NOT NULL,
+    information TEXT NOT NULL,
+    quote TEXT NOT NULL,
+    PRIMARY KEY (run_id, symbol)
+);
+CREATE INDEX IF NOT EXISTS snapshots_symbol ON snapshots (symbol, fetched_at);
+CREATE TABLE IF NOT EXISTS reports (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    symbol TEXT NOT NULL,
+    passed INTEGER NOT NULL,
+    report TEXT NOT NULL,
+    PRIMARY KEY (run_id, symbol)
+);
+CREATE TABLE IF NOT EXISTS criterion_results (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    symbol TEXT NOT NULL,
+    name TEXT NOT NULL,
+    passed INTEGER NOT NULL,
+    required INTEGER NOT NULL,
+    value REAL,
+    lower_limit REAL,
+    upper_limit REAL,
+    error TEXT,
+    PRIMARY KEY (run_id, symbol, name)
+);
+CREATE TABLE IF NOT EXISTS failures (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    symbol TEXT NOT NULL,
+    error TEXT NOT NULL,
+    PRIMARY KEY (run_id, symbol)
+);
+CREATE TABLE IF NOT EXISTS watchlist (
+    symbol TEXT PRIMARY KEY,
+    note TEXT NOT NULL,
+    target_price REAL,
+    added_at INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS alert_state (
+    rule TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    active INTEGER NOT NULL,
+    last_sent_at INTEGER,
+    PRIMARY KEY (rule, symbol)
+);
+CREATE TABLE IF NOT EXISTS job_state (
+    name TEXT PRIMARY KEY,
+    last_run_at INTEGER NOT NULL,
+    last_run_id INTEGER,
+    last_error TEXT
+);
+";
+
+pub struct Storage {
+    connection: Connection,
+}
+
+impl Storage {
+    pub fn open(database_path: &str) -> Result<Self, Error> {
+        Self::with_connection(Connection::open(database_path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, Error> {
+        Self::with_connection(Connection::open_in_memory()?)
+    }
+
+    fn with_connection(connection: Connection) -> Result<Self, Error> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    pub fn begin_run(&self, kind: &str, analysis_config: &AnalysisConfig) -> Result<i64, Error> {
+        self.connection.execute(
+            "INSERT INTO runs (kind, started_at, analysis_config) VALUES (?1, ?2, ?3)",
+            params![
+                kind,
+                unix_timestamp(),
+                serde_json::to_string(analysis_config)?
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    pub fn finish_run(&self, run_id: i64) -> Result<(), Error> {
+        self.connection.execute(
+            "UPDATE runs SET finished_at = ?1 WHERE id = ?2",
+            params![unix_timestamp(), run_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn save_report(&mut self, run_id: i64, report: &StockReport) -> Result<(), Error> {
+        let symbol = &report.stock_info.symbol;
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO snapshots (run_id, symbol, fetched_at, financials, information, quote)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                run_id,
+                symbol,
+                unix_timestamp(),
+                serde_json::to_string(&report.financials)?,
+                serde_json::to_string(&report.information)?,
+                serde_json::to_string(&report.quote)?,
+            ],
+        )?;
+        transaction.execute(
+            "INSERT OR REPLACE INTO reports (run_id, symbol, passed, report) VALUES (?1, ?2, ?3, ?4)",
+            params![run_id, symbol, report.passed, serde_json::to_string(report)?],
+        )?;
+        for criterion in &report.criteria {
+            transaction.execute(
+                "INSERT OR REPLACE INTO criterion_results
+                 (run_id, symbol, name, passed, required, value, lower_limit, upper_limit, error)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    run_id,
+                    symbol,
+                    criterion.name,
+                    criterion.passed,
+                    criterion.required,
+                    criterion.value,
+                    criterion.lower_limit,
+                    criterion.upper_limit,
+                    criterion.error,
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    pub fn save_failure(&self, run_id: i64, symbol: &str, error: &str) -> Result<(), Error> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO failures (run_id, symbol, error) VALUES (?1, ?2, ?3)",
+            params![run_id, symbol, error],
+        )?;
+        Ok(())
+    }
+
+    pub fn runs(&self) -> Result<Vec<RunSummary>, Error> {
+        let mut statement = self
+            .connection
+            .prepare(&format!("{RUN_SUMMARY_QUERY} ORDER BY id"))?;
+        let runs = statement
+            .query_map([], run_summary_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(runs)
+    }
+
+    pub fn run(&self, run_id: i64) -> Result<Option<RunSummary>, Error> {
+        let run = self
+            .connection
+            .query_row(
+                &format!("{RUN_SUMMARY_QUERY} WHERE id = ?1"),
+                params![run_id],
+                run_summary_from_row,
+            )
+            .optional()?;
+        Ok(run)
+    }
+
+    pub fn latest_run(&self, kind: &str) -> Result<Option<RunSummary>, Error> {
+        let run = self
+            .connection
+            .query_row(
+                &format!("{RUN_SUMMARY_QUERY} WHERE kind = ?1 ORDER BY id DESC LIMIT 1"),
+                params![kind],
+                run_summary_from_row,
+            )
+            .optional()?;
+        Ok(run)
+    }
+
+    pub fn run_analysis_config(&self, run_id: i64) -> Result<AnalysisConfig, Error> {
+        let analysis_config: String = self.connection.query_row(
+            "SELECT analysis_config FROM runs WHERE id = ?1",
+            params![run_id],
+            |row| row.get(0),
+        )?;
+        Ok(serde_json::from_str(&analysis_config)?)
+    }
+
+    pub fn run_reports(&self, run_id: i64) -> Result<Vec<StockReport>, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT report FROM reports WHERE run_id = ?1 ORDER BY symbol")?;
+        let reports = statement
+            .query_map(params![run_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        reports
+            .iter()
+            .map(|report| Ok(serde_json::from_str(report)?))
+            .collect()
+    }
+
+    // The report of the latest run that analyzed the symbol, quote refreshes included
+    pub fn latest_report(&self, symbol: &str) -> Result<Option<StockReport>, Error> {
+        let report = self
+            .connection
+            .query_row(
+                "SELECT report FROM reports WHERE symbol = ?1 ORDER BY run_id DESC LIMIT 1",
+                params![symbol],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        report
+            .map(|report| Ok(serde_json::from_str(&report)?))
+            .transpose()
+    }
+
+    pub fn latest_snapshot(&self, symbol: &str) -> Result<Option<Snapshot>, Error> {
+        let mut snapshots = self.snapshots(symbol)?;
+        Ok(snapshots.pop())
+    }
+
+    // Adds the symbol or replaces its note and target price
+    pub fn add_to_watchlist(&self, entry: &WatchlistEntry) -> Result<(), Error> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO watchlist (symbol, note, target_price, added_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![entry.symbol, entry.note, entry.target_price, entry.added_at],
+        )?;
+        Ok(())
+    }
+
+    // Returns false when the symbol was not on the watchlist
+    pub fn remove_from_watchlist(&self, symbol: &str) -> Result<bool, Error> {
+        let num_removed = self
+            .connection
+            .execute("DELETE FROM watchlist WHERE symbol = ?1", params![symbol])?;
+        Ok(num_removed > 0)
+    }
+
+    pub fn watchlist(&self) -> Result<Vec<WatchlistEntry>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT symbol, note, target_price, added_at FROM watchlist ORDER BY symbol",
+        )?;
+        let entries = statement
+            .query_map([], |row| {
+                Ok(WatchlistEntry {
+                    symbol: row.get(0)?,
+                    note: row.get(1)?,
+                    target_price: row.get(2)?,
+                    added_at: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    // An alert stays active from when it is sent until its condition no longer holds
+    pub fn is_alert_active(&self, rule: &str, symbol: &str) -> Result<bool, Error> {
+        let active = self
+            .connection
+            .query_row(
+                "SELECT active FROM alert_state WHERE rule = ?1 AND symbol = ?2",
+                params![rule, symbol],
+                |row| row.get::<_, bool>(0),
+            )
+            .optional()?;
+        Ok(active.unwrap_or(false))
+    }
+
+    pub fn set_alert_active(&self, rule: &str, symbol: &str, active: bool) -> Result<(), Error> {
+        if active {
+            self.connection.execute(
+                "INSERT OR REPLACE INTO alert_state (rule, symbol, active, last_sent_at)
+                 VALUES (?1, ?2, 1, ?3)",
+                params![rule, symbol, unix_timestamp()],
+            )?;
+        } else {
+            self.connection.execute(
+                "UPDATE alert_state SET active = 0 WHERE rule = ?1 AND symbol = ?2",
+                params![rule, symbol],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn job_state(&self, name: &str) -> Result<Option<JobState>, Error> {
+        let state = self
+            .connection
+            .query_row(
+                "SELECT name, last_run_at, last_run_id, last_error FROM job_state WHERE name = ?1",
+                params![name],
+                |row| {
+                    Ok(JobState {
+                        name: row.get(0)?,
+                        last_run_at: row.get(1)?,
+                        last_run_id: row.get(2)?,
+                        last_error: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+        Ok(state)
+    }
+
+    pub fn save_job_state(&self, state: &JobState) -> Result<(), Error> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO job_state (name, last_run_at, last_run_id, last_error)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                state.name,
+                state.last_run_at,
+                state.last_run_id,
+                state.last_error
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn snapshot_symbols(&self) -> Result<Vec<String>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT DISTINCT symbol FROM snapshots
+                 WHERE run_id NOT IN (SELECT id FROM runs WHERE kind = ?1) ORDER BY symbol",
+        )?;
+        let symbols = statement
+            .query_map(params![QUOTES_RUN_KIND], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(symbols)
+    }
+
+    // Oldest first
+    pub fn snapshots(&self, symbol: &str) -> Result<Vec<Snapshot>, Error> {
+        let mut snapshots = Vec::new();
+        for row in self.snapshot_rows(symbol)? {
+            snapshots.push(Snapshot {
+                run_id: row.run_id,
+                symbol: symbol.to_string(),
+                fetched_at: row.fetched_at,
+                financials: serde_json::from_str(&row.financials)?,
+                information: serde_json::from_str(&row.information)?,
+                quote: serde_json::from_str(&row.quote)?,
+            });
+        }
+        Ok(snapshots)
+    }
+
+    // The metric is a field name of CompanyFinancials, CompanyInformation or CompanyQuote
+    pub fn metric_history(&self, symbol: &str, metric: &str) -> Result<Vec<MetricPoint>, Error> {
+        let mut history = Vec::new();
+        for row in self.snapshot_rows(symbol)? {
+            let mut value = None;
+            for fields in [&row.financials, &row.information, &row.quote] {
+                let fields: serde_json::Value = serde_json::from_str(fields)?;
+                if let Some(field) = fields.get(metric) {
+                    value = Some(field.as_f64());
+                    break;
+                }
+            }
+            let value = value.ok_or_else(|| Error::msg(format!("Unknown metric {metric}")))?;
+            history.push(MetricPoint {
+                run_id: row.run_id,
+                fetched_at: row.fetched_at,
+                value,
+            });
+        }
+        Ok(history)
+    }
+
+    fn snapshot_rows(&self, symbol: &str) -> Result<Vec<SnapshotRow>, Error> {
+        let mut statement = self.connection.prepare(
+            "SELECT run_id, fetched_at, financials, information, quote FROM snapshots
+             WHERE symbol = ?1 AND run_id NOT IN (SELECT id FROM runs WHERE kind = ?2)
+             ORDER BY fetched_at, run_id",
+        )?;
+        let rows = statement
+            .query_map(params![symbol, QUOTES_RUN_KIND], |row| {
+                Ok(SnapshotRow {
+                    run_id: row.get(0)?,
+                    fetched_at: row.get(1)?,
+                    financials: row.get(2)?,
+                    information: row.get(3)?,
+                    quote: row.get(4)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows)
+    }
+}
+
+// Snapshot with the fetched data still serialized
+struct SnapshotRow {
+    run_id: i64,
+    fetched_at: i64,
+    financials: String,
+    information: String,
+    quote: String,
+}
+
+const RUN_SUMMARY_QUERY: &str = "
+SELECT id, kind, started_at, finished_at,
+    (SELECT COUNT(*) FROM reports WHERE reports.run_id = runs.id),
+    (SELECT COUNT(*) FROM reports WHERE reports.run_id = runs.id AND passed),
+    (SELECT COUNT(*) FROM failures WHERE failures.run_id = runs.id)
+FROM runs";
+
+fn run_summary_from_row(row: &rusqlite::Row) -> rusqlite::Result<RunSummary> {
+    Ok(RunSummary {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        started_at: row.get(2)?,
+        finished_at: row.get(3)?,
+        num_stocks: row.get(4)?,
+        num_passed: row.get(5)?,
+        num_failures: row.get(6)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::{analysis_config, report};
+
+    #[test]
+    fn test_run_round_trip() -> Result<()> {
+        let mut storage = Storage::open_in_memory()?;
+        let run_id = storage.begin_run("scan", &analysis_config())?;
+        storage.save_report(run_id, &report("GOOD", 10.0, 30.0))?;
+        storage.save_report(run_id, &report("DEAR", 30.0, 30.0))?;
+        storage.save_failure(run_id, "GONE", "No balance sheet")?;
+        storage.finish_run(run_id)?;
+
+        let runs = storage.runs()?;
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].id, run_id);
+        assert_eq!(runs[0].num_stocks, 2);
+        assert_eq!(runs[0].num_passed, 1);
+        assert_eq!(runs[0].num_failures, 1);
+        assert!(runs[0].finished_at.is_some());
+
+        let reports = storage.run_reports(run_id)?;
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].stock_info.symbol, "DEAR");
+        assert!(!reports[0].passed);
+        assert!(!reports[0].criterion("pe").unwrap().passed);
+        assert!(reports[1].passed);
+
+        assert_eq!(storage.run_analysis_config(run_id)?.pe_limits, [2.0, 22.5]);
+        assert!(storage.run(run_id + 1)?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_latest_snapshot_and_metric_history() -> Result<()> {
+        let mut storage = Storage::open_in_memory()?;
+        for (pe_ratio, price) in [(10.0, 30.0), (12.0, 33.0)] {
+            let run_id = storage.begin_run("scan", &analysis_config())?;
+            storage.save_report(run_id, &report("GOOD", pe_ratio, price))?;
+            storage.finish_run(run_id)?;
+        }
+
+        // Quote refreshes re-evaluate the stored fundamentals and are not snapshots of their own
+        let run_id = storage.begin_run(QUOTES_RUN_KIND, &analysis_config())?;
+        storage.save_report(run_id, &report("GOOD", 12.0, 40.0))?;
+        storage.finish_run(run_id)?;
+
+        let snapshot = storage.latest_snapshot("GOOD")?.unwrap();
+        assert_eq!(snapshot.run_id, 2);
+        assert_eq!(snapshot.quote.c, 33.0);
+        assert!(storage.latest_snapshot("NONE")?.is_none());
+
+        let history = storage.metric_history("GOOD", "pe_ratio")?;
+        let values: Vec<_> = history.iter().map(|point| point.value).collect();
+        assert_eq!(values, vec![Some(10.0), Some(12.0)]);
+
+        let history = storage.metric_history("GOOD", "market_cap")?;
+        assert_eq!(history[1].value, Some(30000.0));
+        let history = storage.metric_history("GOOD", "ps_ratio")?;
+        assert_eq!(history[0].value, None);
+
+        assert!(storage.metric_history("GOOD", "no_such_metric").is_err());
+        Ok(())
+    }
+}
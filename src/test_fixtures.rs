@@ -0,0 +1,88 @@
+// Shared test data, the default stock passes every criterion of the default analysis config
+use crate::financial_analysis::{evaluate_stock, AnalysisConfig, StockReport};
+use crate::stock_data_fetching::{CompanyFinancials, CompanyInformation, CompanyQuote, StockInfo};
+
+pub fn analysis_config() -> AnalysisConfig {
+    AnalysisConfig::from_json_value(serde_json::json!({
+        "pe_limits": [2.0, 22.5],
+        "pb_limits": [0.4, 5.0],
+        "earnings_growth_5y_min": 6.0,
+        "dividend_per_share_min": 0.1,
+        "dividend_growth_5y_min": 5.0,
+        "current_ratio_min": 1.5,
+        "debt_equity_max": 2.0,
+        "market_cap_min": 20e3,
+        "nor_aaa_10y_bond_yield": 0.0295,
+        "us_aaa_10y_bond_yield": 0.0336
+    }))
+}
+
+pub fn stock_info(symbol: &str) -> StockInfo {
+    StockInfo {
+        symbol: symbol.to_string(),
+        currency: "USD".to_string(),
+        description: symbol.to_string(),
+        display_symbol: symbol.to_string(),
+        figi: String::new(),
+        mic: "XNYS".to_string(),
+        security_type: "Common Stock".to_string(),
+    }
+}
+
+pub fn financials(pe_ratio: f64) -> CompanyFinancials {
+    serde_json::from_value(serde_json::json!({
+        "pe_ratio": pe_ratio,
+        "pb_ratio": 1.2,
+        "dividend_per_share": 1.0,
+        "dividend_per_share_5_yr_avg": 0.9,
+        "dividend_growth_5_yr_avg": 6.0,
+        "earnings_per_share": 3.0,
+        "earnings_growth": 4.0,
+        "earnings_growth_5_yr_avg": 7.0,
+        "book_value_per_share": 25.0,
+        "total_debt_to_total_equity": 0.5,
+        "current_ratio": 2.5,
+        "total_current_assets": 50000.0,
+        "total_current_liabilities": 10000.0,
+        "total_current_long_term_debt": 500.0,
+        "total_long_term_debt": 8000.0
+    }))
+    .unwrap()
+}
+
+pub fn information(symbol: &str) -> CompanyInformation {
+    CompanyInformation {
+        name: symbol.to_string(),
+        ticker: symbol.to_string(),
+        exchange: "NYSE".to_string(),
+        currency: "USD".to_string(),
+        country: "US".to_string(),
+        industry: "Retail".to_string(),
+        market_cap: 30000.0,
+        shares_outstanding: 1000.0,
+        ipo: "2000-01-01".to_string(),
+        weburl: String::new(),
+        finnhub_industry: "Retail".to_string(),
+    }
+}
+
+pub fn quote(price: f64) -> CompanyQuote {
+    CompanyQuote {
+        c: price,
+        h: price,
+        l: price,
+        o: price,
+        pc: price,
+        t: 0,
+    }
+}
+
+pub fn report(symbol: &str, pe_ratio: f64, price: f64) -> StockReport {
+    evaluate_stock(
+        &stock_info(symbol),
+        financials(pe_ratio),
+        information(symbol),
+        quote(price),
+        &analysis_config(),
+    )
+}
@@ -0,0 +1,279 @@
+use crate::financial_analysis::criterion_label;
+use crate::result_browser::{PassFilter, ResultBrowser, SortColumn};
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Span, Spans};
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use ratatui::{Frame, Terminal};
+use std::io;
+
+const HELP: &str = "q quit | up/down select | 1-6 sort (again to reverse) | tab choose filter | space cycle filter | pgup/pgdn scroll details";
+
+struct App {
+    browser: ResultBrowser,
+    title: String,
+    filter_cursor: usize, // 0 is the total pass filter, then the criteria
+    detail_scroll: u16,
+}
+
+impl App {
+    fn cycle_focused_filter(&mut self) {
+        match self.filter_cursor {
+            0 => self.browser.cycle_total_filter(),
+            index => self.browser.cycle_criterion_filter(index - 1),
+        }
+        self.detail_scroll = 0;
+    }
+
+    // Returns false when the browser should close
+    fn handle_key(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.browser.select_next();
+                self.detail_scroll = 0;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.browser.select_previous();
+                self.detail_scroll = 0;
+            }
+            KeyCode::Char(digit @ '1'..='6') => {
+                let index = digit as usize - '1' as usize;
+                self.browser.sort_by(SortColumn::ALL[index]);
+            }
+            KeyCode::Tab => {
+                self.filter_cursor = (self.filter_cursor + 1) % (self.browser.criteria().len() + 1)
+            }
+            KeyCode::Char(' ') => self.cycle_focused_filter(),
+            KeyCode::PageDown => self.detail_scroll = self.detail_scroll.saturating_add(10),
+            KeyCode::PageUp => self.detail_scroll = self.detail_scroll.saturating_sub(10),
+            _ => {}
+        }
+        true
+    }
+}
+
+fn format_value(value: Option<f64>) -> String {
+    value.map_or("-".to_string(), |value| format!("{value:.2}"))
+}
+
+fn filter_name(filter: PassFilter) -> &'static str {
+    match filter {
+        PassFilter::Any => "any",
+        PassFilter::Passed => "pass",
+        PassFilter::Failed => "fail",
+    }
+}
+
+fn draw<B: Backend>(frame: &mut Frame<B>, app: &App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(55), Constraint::Percentage(45)])
+        .split(rows[1]);
+
+    // Filter bar
+    let mut filters = vec![("PASSED".to_string(), app.browser.total_filter)];
+    for (name, filter) in app
+        .browser
+        .criteria()
+        .iter()
+        .zip(&app.browser.criterion_filters)
+    {
+        filters.push((criterion_label(name), *filter));
+    }
+    let mut spans = Vec::new();
+    for (index, (label, filter)) in filters.into_iter().enumerate() {
+        let style = if index == app.filter_cursor {
+            Style::default().add_modifier(Modifier::REVERSED)
+        } else {
+            Style::default()
+        };
+        spans.push(Span::styled(
+            format!("{}: {}", label, filter_name(filter)),
+            style,
+        ));
+        spans.push(Span::raw("  "));
+    }
+    let filter_title = format!(
+        "{} | {} of {} stocks",
+        app.title,
+        app.browser.visible_reports().len(),
+        app.browser.num_reports()
+    );
+    frame.render_widget(
+        Paragraph::new(Spans::from(spans))
+            .block(Block::default().borders(Borders::ALL).title(filter_title)),
+        rows[0],
+    );
+
+    // Stock table
+    let header = Row::new(SortColumn::ALL.iter().map(|column| {
+        let mut title = column.title().to_string();
+        if *column == app.browser.sort_column {
+            title += if app.browser.ascending { " ^" } else { " v" };
+        }
+        Cell::from(title)
+    }))
+    .style(Style::default().add_modifier(Modifier::BOLD));
+    let table_rows = app.browser.visible_reports().into_iter().map(|report| {
+        let style = if report.passed {
+            Style::default().fg(Color::Green)
+        } else {
+            Style::default()
+        };
+        Row::new(vec![
+            Cell::from(report.stock_info.symbol.clone()),
+            Cell::from(format_value(SortColumn::Pe.value(report))),
+            Cell::from(format_value(SortColumn::Pb.value(report))),
+            Cell::from(format_value(SortColumn::DebtEquity.value(report))),
+            Cell::from(
+                report
+                    .margin_of_safety
+                    .map_or("-".to_string(), |margin| format!("{:.1}%", 100.0 * margin)),
+            ),
+            Cell::from(
+                SortColumn::GrowthGap
+                    .value(report)
+                    .map_or("-".to_string(), |gap| format!("{:.1}%", 100.0 * gap)),
+            ),
+        ])
+        .style(style)
+    });
+    let widths = [
+        Constraint::Length(10),
+        Constraint::Length(8),
+        Constraint::Length(8),
+        Constraint::Length(13),
+        Constraint::Length(18),
+        Constraint::Length(12),
+    ];
+    let table = Table::new(table_rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title("Stocks"))
+        .widths(&widths)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut table_state = TableState::default();
+    table_state.select(app.browser.selected_index());
+    frame.render_stateful_widget(table, columns[0], &mut table_state);
+
+    // Details of the selected stock
+    let mut lines = Vec::new();
+    if let Some(report) = app.browser.selected_report() {
+        lines.push(Spans::from(Span::styled(
+            format!(
+                "{} {} | price {:.2} | passed: {}",
+                report.stock_info.symbol, report.information.name, report.quote.c, report.passed
+            ),
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        lines.push(Spans::from(format!(
+            "Intrinsic value: {} | Margin of safety: {}",
+            format_value(report.intrinsic_value),
+            report
+                .margin_of_safety
+                .map_or("-".to_string(), |margin| format!("{:.1}%", 100.0 * margin))
+        )));
+        if let Some(implied) = &report.implied_growth {
+            lines.push(Spans::from(format!(
+                "Implied growth: {:.1}% | 5y earnings growth: {}",
+                100.0 * implied.implied_growth,
+                implied
+                    .historical_growth
+                    .map_or("-".to_string(), |growth| format!("{:.1}%", 100.0 * growth))
+            )));
+        }
+        lines.push(Spans::from(""));
+        lines.push(Spans::from(Span::styled(
+            "Criteria",
+            Style::default().add_modifier(Modifier::BOLD),
+        )));
+        for criterion in &report.criteria {
+            let (mark, color) = if criterion.passed {
+                ("pass", Color::Green)
+            } else if criterion.required {
+                ("FAIL", Color::Red)
+            } else {
+                ("fail", Color::Yellow)
+            };
+            lines.push(Spans::from(vec![
+                Span::styled(format!("{mark:<5}"), Style::default().fg(color)),
+                Span::raw(criterion.describe()),
+            ]));
+        }
+        for (title, fields) in [
+            (
+                "Company information",
+                serde_json::to_string_pretty(&report.information),
+            ),
+            (
+                "Company financials",
+                serde_json::to_string_pretty(&report.financials),
+            ),
+        ] {
+            lines.push(Spans::from(""));
+            lines.push(Spans::from(Span::styled(
+                title,
+                Style::default().add_modifier(Modifier::BOLD),
+            )));
+            for line in fields.unwrap_or_default().lines() {
+                lines.push(Spans::from(line.to_string()));
+            }
+        }
+    }
+    frame.render_widget(
+        Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title("Details"))
+            .scroll((app.detail_scroll, 0)),
+        columns[1],
+    );
+
+    frame.render_widget(Paragraph::new(HELP), rows[2]);
+}
+
+// Shows the browser until the user quits, restoring the terminal afterwards
+pub fn browse(browser: ResultBrowser, title: &str) -> Result<()> {
+    let mut app = App {
+        browser,
+        title: title.to_string(),
+        filter_cursor: 0,
+        detail_scroll: 0,
+    };
+
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let result = run(&mut terminal, &mut app);
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+fn run<B: Backend>(terminal: &mut Terminal<B>, app: &mut App) -> Result<()> {
+    loop {
+        terminal.draw(|frame| draw(frame, app))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use crate::stock_data_fetching::StockInfo;
+use anyhow::{Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct UniverseFilterConfig {
+    #[serde(default = "default_include_security_types")]
+    pub include_security_types: Vec<String>, // Empty includes every security type
+    #[serde(default)]
+    pub exclude_security_types: Vec<String>,
+    #[serde(default)]
+    pub mic_whitelist: Vec<String>, // Empty allows every market
+    #[serde(default)]
+    pub exclude_symbol_patterns: Vec<SymbolPattern>,
+    #[serde(default)]
+    pub deny_list: Vec<String>,
+}
+
+fn default_include_security_types() -> Vec<String> {
+    vec!["Common Stock".to_string()]
+}
+
+impl Default for UniverseFilterConfig {
+    fn default() -> Self {
+        Self {
+            include_security_types: default_include_security_types(),
+            exclude_security_types: Vec::new(),
+            mic_whitelist: Vec::new(),
+            exclude_symbol_patterns: Vec::new(),
+            deny_list: Vec::new(),
+        }
+    }
+}
+
+// A regular expression on the symbol, compiled when the config is deserialized so that an invalid
+// pattern fails before the stock list is fetched
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct SymbolPattern {
+    regex: Regex,
+}
+
+impl SymbolPattern {
+    pub fn is_match(&self, symbol: &str) -> bool {
+        self.regex.is_match(symbol)
+    }
+}
+
+impl TryFrom<String> for SymbolPattern {
+    type Error = Error;
+
+    fn try_from(pattern: String) -> Result<Self> {
+        let regex = Regex::new(&pattern)
+            .map_err(|error| Error::msg(format!("Invalid symbol pattern {pattern}: {error}")))?;
+        Ok(Self { regex })
+    }
+}
+
+impl From<SymbolPattern> for String {
+    fn from(pattern: SymbolPattern) -> Self {
+        pattern.regex.as_str().to_string()
+    }
+}
+
+pub struct UniverseFilter {
+    config: UniverseFilterConfig,
+}
+
+impl UniverseFilter {
+    pub fn new(config: &UniverseFilterConfig) -> Self {
+        Self {
+            config: config.clone(),
+        }
+    }
+
+    pub fn accepts(&self, stock_info: &StockInfo) -> bool {
+        let config = &self.config;
+        if !config.include_security_types.is_empty()
+            && !config
+                .include_security_types
+                .contains(&stock_info.security_type)
+        {
+            return false;
+        }
+        if config
+            .exclude_security_types
+            .contains(&stock_info.security_type)
+        {
+            return false;
+        }
+        if !config.mic_whitelist.is_empty() && !config.mic_whitelist.contains(&stock_info.mic) {
+            return false;
+        }
+        if config.deny_list.contains(&stock_info.symbol) {
+            return false;
+        }
+
+        !config
+            .exclude_symbol_patterns
+            .iter()
+            .any(|pattern| pattern.is_match(&stock_info.symbol))
+    }
+
+    pub fn apply(&self, stocks: Vec<StockInfo>) -> Vec<StockInfo> {
+        stocks
+            .into_iter()
+            .filter(|stock_info| self.accepts(stock_info))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_stock(symbol: &str, security_type: &str, mic: &str) -> StockInfo {
+        StockInfo {
+            symbol: symbol.to_string(),
+            currency: "USD".to_string(),
+            description: symbol.to_string(),
+            display_symbol: symbol.to_string(),
+            figi: String::new(),
+            mic: mic.to_string(),
+            security_type: security_type.to_string(),
+        }
+    }
+
+    fn test_stocks() -> Vec<StockInfo> {
+        vec![
+            test_stock("AAPL", "Common Stock", "XNAS"),
+            test_stock("O", "REIT", "XNYS"),
+            test_stock("BABA", "ADR", "XNYS"),
+            test_stock("BAC-PL", "Preference", "XNYS"),
+            test_stock("ABCDW", "Common Stock", "OOTC"),
+        ]
+    }
+
+    fn symbols(stocks: &[StockInfo]) -> Vec<&str> {
+        stocks.iter().map(|stock| stock.symbol.as_str()).collect()
+    }
+
+    #[test]
+    fn test_default_keeps_common_stock_only() -> Result<()> {
+        let universe_filter = UniverseFilter::new(&UniverseFilterConfig::default());
+        let stocks = universe_filter.apply(test_stocks());
+        assert_eq!(symbols(&stocks), vec!["AAPL", "ABCDW"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_reit_only_screen() -> Result<()> {
+        let config: UniverseFilterConfig = serde_json::from_value(serde_json::json!({
+            "include_security_types": ["REIT"]
+        }))?;
+        let stocks = UniverseFilter::new(&config).apply(test_stocks());
+        assert_eq!(symbols(&stocks), vec!["O"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exclusions() -> Result<()> {
+        let config: UniverseFilterConfig = serde_json::from_value(serde_json::json!({
+            "include_security_types": [],
+            "exclude_security_types": ["ADR"],
+            "mic_whitelist": ["XNAS", "XNYS"],
+            "exclude_symbol_patterns": ["-P[A-Z]$"],
+            "deny_list": ["O"]
+        }))?;
+        let stocks = UniverseFilter::new(&config).apply(test_stocks());
+        assert_eq!(symbols(&stocks), vec!["AAPL"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_symbol_pattern() {
+        let error = serde_json::from_value::<UniverseFilterConfig>(serde_json::json!({
+            "exclude_symbol_patterns": ["-P[A-Z]$", "["]
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("Invalid symbol pattern ["));
+    }
+}
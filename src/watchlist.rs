@@ -0,0 +1,237 @@
+use crate::financial_analysis::{evaluate_stock, StockAnalyzer, StockReport};
+use crate::run_diff::{diff_reports, RunDiff};
+use crate::stock_data_fetching::StockInfo;
+use crate::storage::{Storage, WatchlistEntry, QUOTES_RUN_KIND};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchlistItem {
+    pub entry: WatchlistEntry,
+    pub report: Option<StockReport>,
+    pub error: Option<String>, // Set when the stock data could not be fetched
+    pub previous_price: Option<f64>,
+    pub target_price_reached: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WatchlistCheck {
+    pub run_id: i64,
+    pub items: Vec<WatchlistItem>,
+    pub changes: Option<RunDiff>, // None on the first check
+}
+
+pub fn summarize_watchlist(
+    run_id: i64,
+    entries: Vec<WatchlistEntry>,
+    results: Vec<Result<StockReport, String>>,
+    previous_run: Option<(i64, Vec<StockReport>)>,
+) -> WatchlistCheck {
+    let reports: Vec<StockReport> = results
+        .iter()
+        .filter_map(|result| result.as_ref().ok().cloned())
+        .collect();
+
+    let mut items = Vec::new();
+    for (entry, result) in entries.into_iter().zip(results) {
+        let previous_price = previous_run.as_ref().and_then(|(_, previous_reports)| {
+            previous_reports
+                .iter()
+                .find(|report| report.stock_info.symbol == entry.symbol)
+                .map(|report| report.quote.c)
+        });
+        let (report, error) = match result {
+            Ok(report) => (Some(report), None),
+            Err(error) => (None, Some(error)),
+        };
+        let target_price_reached = match (&report, entry.target_price) {
+            (Some(report), Some(target_price)) => report.quote.c <= target_price,
+            _ => false,
+        };
+        items.push(WatchlistItem {
+            entry,
+            report,
+            error,
+            previous_price,
+            target_price_reached,
+        });
+    }
+
+    let changes = previous_run.map(|(previous_run_id, previous_reports)| {
+        diff_reports(previous_run_id, &previous_reports, run_id, &reports)
+    });
+
+    WatchlistCheck {
+        run_id,
+        items,
+        changes,
+    }
+}
+
+// Screens every symbol on the watchlist and stores the results as a "watchlist" run
+pub async fn check_watchlist(
+    analyzer: &mut StockAnalyzer,
+    storage: &mut Storage,
+) -> Result<WatchlistCheck> {
+    let entries = storage.watchlist()?;
+    let previous_run = match storage.latest_run("watchlist")? {
+        Some(run) => Some((run.id, storage.run_reports(run.id)?)),
+        None => None,
+    };
+    let run_id = storage.begin_run("watchlist", &analyzer.analysis_config)?;
+
+    let mut results = Vec::new();
+    for entry in &entries {
+        match analyzer
+            .analyze_stock(&StockInfo::from_symbol(&entry.symbol))
+            .await
+        {
+            Ok(mut report) => {
+                report.stock_info.description = report.information.name.clone();
+                report.stock_info.currency = report.information.currency.clone();
+                storage.save_report(run_id, &report)?;
+                results.push(Ok(report));
+            }
+            Err(error) => {
+                println!("Error checking stock {}: {}", entry.symbol, error);
+                storage.save_failure(run_id, &entry.symbol, &error.to_string())?;
+                results.push(Err(error.to_string()));
+            }
+        }
+    }
+    storage.finish_run(run_id)?;
+
+    Ok(summarize_watchlist(run_id, entries, results, previous_run))
+}
+
+// Fetches only the latest quote of every watched symbol and re-evaluates it against the most
+// recently stored fundamentals. Symbols without stored fundamentals are screened in full.
+// The results are stored as a QUOTES_RUN_KIND run, whose id is returned.
+pub async fn refresh_watchlist_quotes(
+    analyzer: &mut StockAnalyzer,
+    storage: &mut Storage,
+) -> Result<i64> {
+    let entries = storage.watchlist()?;
+    let run_id = storage.begin_run(QUOTES_RUN_KIND, &analyzer.analysis_config)?;
+
+    for entry in &entries {
+        let stock_info = StockInfo::from_symbol(&entry.symbol);
+        let result = match storage.latest_snapshot(&entry.symbol)? {
+            Some(snapshot) => analyzer.get_quote(&stock_info).await.map(|quote| {
+                evaluate_stock(
+                    &stock_info,
+                    snapshot.financials,
+                    snapshot.information,
+                    quote,
+                    &analyzer.analysis_config,
+                )
+            }),
+            None => analyzer.analyze_stock(&stock_info).await,
+        };
+        match result {
+            Ok(mut report) => {
+                report.stock_info.description = report.information.name.clone();
+                report.stock_info.currency = report.information.currency.clone();
+                storage.save_report(run_id, &report)?;
+            }
+            Err(error) => {
+                println!("Error refreshing quote of {}: {}", entry.symbol, error);
+                storage.save_failure(run_id, &entry.symbol, &error.to_string())?;
+            }
+        }
+    }
+    storage.finish_run(run_id)?;
+
+    Ok(run_id)
+}
+
+impl WatchlistCheck {
+    pub fn to_table(&self) -> String {
+        let mut table = format!(
+            "{:<8} {:>10} {:>10} {:>10} {:<6} {}\n",
+            "SYMBOL", "PRICE", "PREVIOUS", "TARGET", "PASSED", "NOTE"
+        );
+        for item in &self.items {
+            let format_price =
+                |price: Option<f64>| price.map_or("-".to_string(), |price| format!("{price:.2}"));
+            let passed = match (&item.report, &item.error) {
+                (Some(report), _) => report.passed.to_string(),
+                (None, Some(_)) => "error".to_string(),
+                (None, None) => "-".to_string(),
+            };
+            let mut note = item.entry.note.clone();
+            if item.target_price_reached {
+                note = format!("TARGET PRICE REACHED {note}");
+            }
+            table += &format!(
+                "{:<8} {:>10} {:>10} {:>10} {:<6} {}\n",
+                item.entry.symbol,
+                format_price(item.report.as_ref().map(|report| report.quote.c)),
+                format_price(item.previous_price),
+                format_price(item.entry.target_price),
+                passed,
+                note.trim_end(),
+            );
+        }
+        match &self.changes {
+            Some(changes) => table += &format!("\n{}", changes.to_table()),
+            None => table += "\nNo previous watchlist check to compare with\n",
+        }
+        table
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_fixtures::report;
+
+    #[test]
+    fn test_watchlist_storage() -> Result<()> {
+        let storage = Storage::open_in_memory()?;
+        storage.add_to_watchlist(&WatchlistEntry::new("msft", "Cloud", None))?;
+        storage.add_to_watchlist(&WatchlistEntry::new("AAPL", "Buyback", Some(120.0)))?;
+        storage.add_to_watchlist(&WatchlistEntry::new("AAPL", "Services", Some(110.0)))?;
+
+        let entries = storage.watchlist()?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].symbol, "AAPL");
+        assert_eq!(entries[0].note, "Services");
+        assert_eq!(entries[0].target_price, Some(110.0));
+        assert_eq!(entries[1].symbol, "MSFT");
+
+        assert!(storage.remove_from_watchlist("MSFT")?);
+        assert!(!storage.remove_from_watchlist("MSFT")?);
+        assert_eq!(storage.watchlist()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_summarize_watchlist() {
+        let entries = vec![
+            WatchlistEntry::new("CHEAP", "", Some(35.0)),
+            WatchlistEntry::new("DEAR", "Wait for a dip", Some(20.0)),
+            WatchlistEntry::new("GONE", "", None),
+        ];
+        let results = vec![
+            Ok(report("CHEAP", 12.0, 30.0)),
+            Ok(report("DEAR", 30.0, 30.0)),
+            Err("No balance sheet".to_string()),
+        ];
+        let previous_reports = vec![report("CHEAP", 25.0, 40.0), report("DEAR", 12.0, 28.0)];
+
+        let check = summarize_watchlist(2, entries, results, Some((1, previous_reports)));
+        assert!(check.items[0].target_price_reached);
+        assert_eq!(check.items[0].previous_price, Some(40.0));
+        assert!(!check.items[1].target_price_reached);
+        assert_eq!(check.items[2].error.as_deref(), Some("No balance sheet"));
+
+        let changes = check.changes.as_ref().unwrap();
+        assert_eq!(changes.entered[0].symbol, "CHEAP");
+        assert_eq!(changes.left[0].symbol, "DEAR");
+        assert!(check.to_table().contains("TARGET PRICE REACHED"));
+
+        let first_check = summarize_watchlist(1, Vec::new(), Vec::new(), None);
+        assert!(first_check.changes.is_none());
+    }
+}